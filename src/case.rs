@@ -1,17 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use crate::prawitz::BoundMethod;
 use crate::restriction::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Hypothesis {
     DeltaBound(f64, f64),
+    // A list of (target, tolerance): every a_i must be within the tolerance of
+    // one of the targets.
+    WeightedDeltaBound(Vec<(f64, f64)>),
     SumLowerBound(Vec<i32>, f64),
     Contradiction,
 }
 
+impl Hypothesis {
+    /**
+     * The name under which the outcome of this hypothesis is reported.
+     */
+    pub fn name(&self) -> &'static str {
+	match self {
+	    Hypothesis::DeltaBound(..) => "delta",
+	    Hypothesis::WeightedDeltaBound(..) => "near",
+	    Hypothesis::SumLowerBound(..) => "sum",
+	    Hypothesis::Contradiction => "contradiction",
+	}
+    }
+}
+
+/**
+ * One of the subcases into which the output of a case is split. If it is named,
+ * then the name is used in the output in place of its positional letter.
+ */
+#[derive(Debug, Clone)]
+pub struct Subcase {
+    pub name: Option<String>,
+    pub restrictions: Vec<Restriction>,
+}
+
 /**
  * This stores all the information about an instance of the problem, and how it
  * is to be run.
  * This structure is produced in file_io.rs
  */
+#[derive(Clone)]
 pub struct Case {
     pub threshold: f64,
     pub prob_cutoff: f64,
@@ -19,27 +50,247 @@ pub struct Case {
     pub denominator: u128,
     pub bounds: Vec<Interval>,
     pub restrictions: Vec<Restriction>,
-    pub subcases: Vec<Vec<Restriction>>,
+    pub subcases: Vec<Subcase>,
     pub hypotheses: Vec<Hypothesis>,
+    // A probability must beat prob_cutoff by this much for a node to be resolved.
+    pub resolve_epsilon: f64,
+    // Which bounds the search may use when resolving a node.
+    pub method: BoundMethod,
+    // Whether the full method also considers Bernstein's inequality and the
+    // Chernoff bound, which may be turned off for cases where they never help.
+    pub use_bernstein: bool,
+    // Labels for organising cases by topic, which do not affect the search.
+    pub tags: Vec<String>,
+}
+
+// To mitigate risk of floating-point errors.
+pub const RESOLVE_EPSILON: f64 = 0.0000000001;
+
+// Beyond this, not every numerator can be represented exactly as an f64.
+pub const MAX_EXACT_DENOMINATOR: u128 = 1 << 53;
+
+/**
+ * Whether max_depth squares of numerators up to denominator + 1 (the top of the
+ * last interval) can be summed in a u128, along with denominator^2 itself.
+ */
+pub fn square_sums_fit(denominator: u128, max_depth: usize) -> bool {
+    denominator.checked_add(1)
+	.and_then(|top| top.checked_mul(top))
+	.and_then(|square| square.checked_mul(max_depth.max(1) as u128))
+	.is_some()
+}
+
+/**
+ * Computes floor(x * denominator), for non-negative x. For denominators up to
+ * 2^53 the product of floats rounds decimal inputs such as 0.3 * 10 to the
+ * intended integer. Beyond that the product of floats is inexact, so we instead
+ * decompose x as mantissa * 2^exponent and do the multiplication in integers,
+ * falling back to floating point only if that would overflow.
+ */
+pub fn scale_to_numerator(x: f64, denominator: u128) -> u128 {
+    if x <= 0.0 {
+	return 0;
+    } else if denominator <= MAX_EXACT_DENOMINATOR {
+	return (x * (denominator as f64)) as u128;
+    }
+    let bits = x.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = (bits & ((1 << 52) - 1)) as u128;
+    let (mantissa, exponent) = if biased_exponent == 0 {
+	(fraction, -1074)
+    } else {
+	(fraction | (1 << 52), biased_exponent - 1075)
+    };
+    match mantissa.checked_mul(denominator) {
+	Some(product) if exponent <= 0 => {
+	    if exponent <= -128 { 0 } else { product >> (-exponent) }
+	}
+	_ => (x * (denominator as f64)) as u128,
+    }
 }
 
 impl Case {
+    /**
+     * A copy of this case with a different denominator. Note that Equals gives a
+     * numerator, so it refers to a different interval under the new denominator.
+     */
+    pub fn with_denominator(&self, denominator: u128) -> Case {
+	Case { denominator, ..self.clone() }
+    }
+
+    /**
+     * A copy of this case searched to a different depth. Note that the hypotheses
+     * are then about a different number of the a_i.
+     */
+    pub fn with_max_depth(&self, max_depth: usize) -> Case {
+	Case { max_depth, ..self.clone() }
+    }
+
+    /**
+     * A copy of this case with a different threshold s in P[ X >= s ] >= p.
+     */
+    pub fn with_threshold(&self, threshold: f64) -> Case {
+	Case { threshold, ..self.clone() }
+    }
+
+    /**
+     * A copy of this case which requires a different margin over prob_cutoff
+     * before a node is resolved.
+     */
+    pub fn with_resolve_epsilon(&self, resolve_epsilon: f64) -> Case {
+	Case { resolve_epsilon, ..self.clone() }
+    }
+
+    /**
+     * A copy of this case which is searched using only the bounds allowed by method.
+     */
+    pub fn with_method(&self, method: BoundMethod) -> Case {
+	Case { method, ..self.clone() }
+    }
+
     /**
      * This returns the lower bound we have on the numerator of a_depth in this case
      */
     pub fn get_lower_bound(&self, depth: usize) -> u128 {
 	if let Some(interval) = self.bounds.get(depth) {
-	    (interval.lb * (self.denominator as f64)) as u128
+	    scale_to_numerator(interval.lb, self.denominator)
 	} else {
 	    0
 	}
     }
 
+    /**
+     * The numerator of the interval containing x, i.e. floor(x * denominator).
+     */
+    pub fn numerator_of(&self, x: f64) -> u128 {
+	scale_to_numerator(x, self.denominator)
+    }
+
+    /**
+     * The smallest possible value of the sum of the squares of the numerators
+     * from index start up to max_depth, using only the lower bounds on each a_i.
+     */
+    pub fn min_square_sum_from(&self, start: usize) -> u128 {
+	(start..self.max_depth).map(|i| self.get_lower_bound(i).pow(2)).sum()
+    }
+
+    /**
+     * The first index from which a ZeroAfter restriction forces every numerator to
+     * be zero, if there is one.
+     */
+    pub fn zero_after(&self) -> Option<usize> {
+	self.restrictions.iter().filter_map(|restriction| match restriction {
+	    Restriction::ZeroAfter(start) => Some(*start),
+	    _ => None,
+	}).min()
+    }
+
     pub fn get_upper_bound(&self, depth: usize) -> u128 {
-	if let Some(interval) = self.bounds.get(depth) {
-	    (interval.ub * (self.denominator as f64)) as u128
+	if self.zero_after().is_some_and(|start| depth >= start) {
+	    // Then we don't iterate over any non-zero numerators at this depth.
+	    0
+	} else if let Some(interval) = self.bounds.get(depth) {
+	    scale_to_numerator(interval.ub, self.denominator)
 	} else {
 	    self.denominator - 1
 	}
     }
+
+    /**
+     * The depths at which no numerator lies between get_lower_bound and
+     * get_upper_bound, so that the search skips everything below them. This
+     * happens when the bounds on a_i are inconsistent, perhaps after combining
+     * those from an Include, or when ZeroAfter forces a_i to 0 below its lower
+     * bound, and is almost always a mistake.
+     */
+    pub fn empty_numerator_ranges(&self) -> Vec<usize> {
+	(0..self.max_depth)
+	    .filter(|depth| self.get_lower_bound(*depth) > self.get_upper_bound(*depth))
+	    .collect()
+    }
+
+    /**
+     * For s > 0, symmetry gives P[ X >= s ] <= 1/2, so if p >= 1/2 then no node can
+     * be resolved, and a run would only find that nothing can be proved.
+     */
+    pub fn cannot_be_proved(&self) -> bool {
+	self.threshold > 0.0 && self.prob_cutoff + self.resolve_epsilon > 0.5
+    }
+
+    /**
+     * Prints a warning for each depth given by empty_numerator_ranges.
+     */
+    pub fn warn_empty_numerator_ranges(&self, name: &str) {
+	for depth in self.empty_numerator_ranges() {
+	    println!("WARNING: in case {} with denominator {}, no numerator of a_{} lies in [{}, {}], so nothing will be searched!",
+		     name, self.denominator, depth, self.get_lower_bound(depth),
+		     self.get_upper_bound(depth));
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numerators_are_exact_near_and_beyond_2_53() {
+	// At 2^53 the product of floats is still exact.
+	assert_eq!(scale_to_numerator(0.5, MAX_EXACT_DENOMINATOR), 1 << 52);
+	assert_eq!(scale_to_numerator(0.3, 10), 3);
+	// The f64 nearest to 0.1 is 3602879701896397 / 2^55.
+	let mantissa_of_tenth: u128 = 3602879701896397;
+	for denominator in [MAX_EXACT_DENOMINATOR + 1, 100_000_000_000_000_001] {
+	    assert_eq!(scale_to_numerator(0.1, denominator), (mantissa_of_tenth * denominator) >> 55,
+		       "at denominator {}", denominator);
+	}
+	assert_eq!(scale_to_numerator(0.1, 1 << 80), mantissa_of_tenth << 25);
+	assert_eq!(scale_to_numerator(0.75, 1 << 70), 3 << 68);
+	assert_eq!(scale_to_numerator(0.0, 1 << 70), 0);
+    }
+
+    #[test]
+    fn square_sums_fit_at_the_boundary() {
+	// For depth 1 the top of the last interval, d + 1, must be below 2^64.
+	assert!(square_sums_fit((1 << 64) - 2, 1));
+	assert!(!square_sums_fit((1 << 64) - 1, 1));
+	// For depth 4 it must be below 2^63.
+	assert!(square_sums_fit((1 << 63) - 2, 4));
+	assert!(!square_sums_fit((1 << 63) - 1, 4));
+	assert!(!square_sums_fit(u128::MAX, 1));
+    }
+
+    #[test]
+    fn empty_numerator_ranges_at_denominator_2() {
+	let case = |bounds: Vec<Interval>, restrictions: Vec<Restriction>| Case {
+	    threshold: 0.5, prob_cutoff: 0.3, max_depth: 3, denominator: 2, bounds,
+	    restrictions, subcases: vec![], hypotheses: vec![], resolve_epsilon: RESOLVE_EPSILON,
+	    method: BoundMethod::Full, use_bernstein: true, tags: vec![],
+	};
+	// A narrow interval still lies in the interval [0, 1/2] of a single numerator.
+	let narrow = case(vec![Interval { lb: 0.3, ub: 0.4 }], vec![]);
+	assert_eq!((narrow.get_lower_bound(0), narrow.get_upper_bound(0)), (0, 0));
+	assert!(narrow.empty_numerator_ranges().is_empty());
+	let inverted = case(vec![Interval { lb: 0.3, ub: 0.4 }, Interval { lb: 0.6, ub: 0.4 }],
+			    vec![]);
+	assert_eq!(inverted.empty_numerator_ranges(), [1]);
+	let zeroed = case(vec![Interval { lb: 0.0, ub: 1.0 }, Interval { lb: 0.5, ub: 0.9 }],
+			  vec![Restriction::ZeroAfter(1)]);
+	assert_eq!(zeroed.empty_numerator_ranges(), [1]);
+    }
+
+    #[test]
+    fn half_cutoff_at_positive_threshold_cannot_be_proved() {
+	let case = |threshold: f64, prob_cutoff: f64| Case {
+	    threshold, prob_cutoff, max_depth: 3, denominator: 10, bounds: vec![],
+	    restrictions: vec![], subcases: vec![], hypotheses: vec![],
+	    resolve_epsilon: RESOLVE_EPSILON, method: BoundMethod::Full, use_bernstein: true,
+	    tags: vec![],
+	};
+	assert!(case(0.5, 0.5).cannot_be_proved());
+	assert!(case(0.5, 0.5 - RESOLVE_EPSILON / 2.0).cannot_be_proved());
+	assert!(!case(0.5, 0.49).cannot_be_proved());
+	// Below 0 the probability can be well above 1/2.
+	assert!(!case(-0.5, 0.5).cannot_be_proved());
+    }
 }
@@ -1,6 +1,9 @@
+use serde::{Deserialize, Serialize};
+
 use crate::case::*;
 use crate::restriction::*;
 use crate::prover::Seq;
+use crate::util::*;
 
 /**
  * Stores a record of the maximal/minimal values encountered in the simulation.
@@ -18,11 +21,33 @@ pub struct Extrema {
  * There is one Results structure for each subcase the program runs on.
  */
 pub struct Results {
-    subcases: Vec<(Vec<Restriction>, Extrema)>,
+    subcases: Vec<(Subcase, Extrema)>,
     sum_lower_bound_coefs: Vec<Vec<i32>>,
     default_subcase: Extrema,
 }
 
+/**
+ * Everything found for a single subcase, for the JSON document of a run.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct SubcaseDocument {
+    pub label: String,
+    pub restrictions: Vec<Restriction>,
+    pub contradiction: bool,
+    pub intervals: Vec<Interval>,
+    pub sum_lower_bounds: Vec<Option<f64>>,
+}
+
+/**
+ * The distance of an interval from the nearest of a list of (target, tolerance),
+ * increased by error and then divided by that tolerance.
+ */
+fn weighted_delta(interval: &Interval, targets: &[(f64, f64)], error: f64) -> f64 {
+    targets.iter().map(|(target, tolerance)| {
+	((target - interval.lb).abs().max((interval.ub - target).abs()) + error) / tolerance
+    }).fold(f64::INFINITY, f64::min)
+}
+
 impl Extrema {
     pub fn new(denominator: u128, max_depth: usize, num_sums: usize) -> Extrema {
         Extrema {
@@ -66,26 +91,44 @@ impl Extrema {
     }
 
     /**
-     * Returns the maximum distance of any of the intervals from one of the
-     * possible difficult cases (i.e. 0, 1/4, 1/3, 1/2, 2/3, 1)
+     * Includes everything included in another Extrema for the same subcase.
+     */
+    pub fn merge(&mut self, other: Extrema) {
+	for (i, (min, max)) in other.min_as.iter_numerators()
+	    .zip(other.max_as.iter_numerators()).enumerate() {
+		self.min_as.set(i, self.min_as.get_min_numerator(i).min(*min));
+		self.max_as.set(i, self.max_as.get_min_numerator(i).max(*max));
+	    }
+	for (bound, other_bound) in self.sum_lower_bounds.iter_mut().zip(other.sum_lower_bounds) {
+	    *bound = match (*bound, other_bound) {
+		(Some(x), Some(y)) => Some(x.min(y)),
+		(x, None) | (None, x) => x,
+	    };
+	}
+    }
+
+    /**
+     * Returns the maximum over the intervals of the distance from the nearest of
+     * a list of (target, tolerance), increased by error and then divided by that
+     * tolerance, along with the index achieving it and the interval at that index.
+     * So the result is at most 1 exactly when every interval is within its
+     * tolerance of some target, allowing for error.
      */
-    pub fn get_max_delta(&self, target: f64, depth: usize) -> f64 {
+    pub fn get_max_weighted_delta(&self, targets: &[(f64, f64)], error: f64,
+				  depth: usize) -> (f64, usize, Interval) {
 	let mut max_delta: f64 = 0.0;
+	let mut worst_index = 0;
+	let mut worst_interval = Interval::UNIT;
 	for i in 0..depth {
-	    let min = self.min_as.get_min(i);
-	    let max = self.max_as.get_max(i);
-	    let mut min_delta = 1.0;
-	    for target in [0.0, target, 2.0 * target] {
-		let delta = (target - min).abs().max((max - target).abs());
-		if delta < min_delta {
-		    min_delta = delta;
-		}
-	    }
+	    let interval = Interval { lb: self.min_as.get_min(i), ub: self.max_as.get_max(i) };
+	    let min_delta = weighted_delta(&interval, targets, error);
 	    if min_delta > max_delta {
 		max_delta = min_delta;
+		worst_index = i;
+		worst_interval = interval;
 	    }
 	}
-	max_delta
+	(max_delta, worst_index, worst_interval)
     }
 
     /**
@@ -104,46 +147,71 @@ impl Extrema {
     }
 
     /**
-     * This prints this structure in a human-readable format.
+     * The interval found for each a_i, intersected with the given bounds.
+     */
+    pub fn intervals(&self, bounds: &[Interval]) -> Vec<Interval> {
+	self.min_as.iter_numerators().zip(self.max_as.iter_numerators()).enumerate()
+	    .map(|(index, (lower, upper))| {
+		let found = Interval {
+		    lb: (*lower as f64) / (self.denominator as f64),
+		    ub: ((*upper + 1) as f64) / (self.denominator as f64),
+		};
+		found.intersect(bounds.get(index).unwrap_or(&Interval::UNIT))
+	    }).collect()
+    }
+
+    /**
+     * This prints this structure in a human-readable format, rounding the intervals
+     * outwards to the given precision if there is one.
      */
-    pub fn print(&self, bounds: &Vec<Interval>) {
+    pub fn print(&self, bounds: &Vec<Interval>, precision: Option<usize>) {
 	if self.is_contradiction() {
 	    println!("Case resolved: no sequence can satisfy given conditions!");
 	} else {
-            for (index, (lower, upper)) in self.min_as.iter_numerators().zip(self.max_as.iter_numerators()).enumerate() {
-		let interval = bounds.get(index).unwrap_or(&Interval::UNIT);
-		let lb = ((*lower as f64) / (self.denominator as f64)).max(interval.lb);
-		let ub = (((*upper + 1) as f64) / (self.denominator as f64))
-		    .min(interval.ub);
-		println!("{} <= a_{} <= {}", lb, index, ub);
+	    for (index, interval) in self.intervals(bounds).iter().enumerate() {
+		println!("{} <= a_{} <= {}", format_float(interval.lb, precision, f64::floor),
+			 index, format_float(interval.ub, precision, f64::ceil));
             }
 	}
     }
 
     /**
      * This prints the Extrema in a format which can be immediately recycled to
-     * run again.
+     * run again. The subcases are printed as Subcase lines; this is used for the
+     * default subcase, so that re-running it splits off the same subcases again,
+     * rather than mixing them in with what was the default subcase.
      */
-    pub fn print_machine(&self, case: &Case, subcase: &Vec<Restriction>) {
+    pub fn print_machine(&self, case: &Case, subcase: &Vec<Restriction>,
+			 subcases: &[Subcase]) {
+	print!("{}", self.machine_text(case, subcase, subcases));
+    }
+
+    /**
+     * The lines printed by print_machine.
+     */
+    pub fn machine_text(&self, case: &Case, subcase: &[Restriction],
+			subcases: &[Subcase]) -> String {
 	if self.min_as.get_min(0) > self.max_as.get_max(0) {
-	    println!("Case resolved: no sequence can satisfy given conditions!");
-	} else {
-	    println!("{}, {}, {}, {}", case.threshold, case.prob_cutoff,
-		     case.max_depth, case.denominator);
-	    for restriction in case.restrictions.iter() {
-		println!("{:?}", restriction);
-	    }
-	    for restriction in subcase.iter() {
-		println!("{:?}", restriction);
-	    }
-            for (index, (lower, upper)) in self.min_as.iter_numerators().zip(self.max_as.iter_numerators()).enumerate() {
-		let interval = case.bounds.get(index).unwrap_or(&Interval::UNIT);
-		let lb = ((*lower as f64) / (self.denominator as f64)).max(interval.lb);
-		let ub = (((*upper + 1) as f64) / (self.denominator as f64))
-		    .min(interval.ub);
-		println!("Bounds({}, {}, {})", index, lb, ub);
-            }
+	    return String::from("Case resolved: no sequence can satisfy given conditions!\n");
+	}
+	let mut text = format!("{}, {}, {}, {}\n", case.threshold, case.prob_cutoff,
+			       case.max_depth, case.denominator);
+	for restriction in case.restrictions.iter() {
+	    text += &format!("{:?}\n", restriction);
+	}
+	for restriction in subcase.iter() {
+	    text += &format!("{:?}\n", restriction);
 	}
+	for subcase in subcases.iter() {
+	    let name = subcase.name.iter().map(|name| format!("name={}", name));
+	    let restrictions = subcase.restrictions.iter()
+		.map(|restriction| format!("{:?}", restriction));
+	    text += &format!("Subcase({})\n", name.chain(restrictions).collect::<Vec<String>>().join(", "));
+	}
+	for (index, interval) in self.intervals(&case.bounds).iter().enumerate() {
+	    text += &format!("Bounds({}, {}, {})\n", index, interval.lb, interval.ub);
+	}
+	text
     }
 }
 
@@ -174,7 +242,7 @@ impl Results {
     pub fn include_seq(&mut self, seq: &Seq, depth: usize) {
         let mut is_in_any_subcase = false;
         for (subcase, extrema) in self.subcases.iter_mut() {
-            if seq.could_satisfy_restrictions(subcase, depth) {
+	    if seq.could_satisfy_restrictions(&subcase.restrictions, depth) {
                 extrema.include_seq(seq, &self.sum_lower_bound_coefs, depth);
                 is_in_any_subcase = true;
             }
@@ -184,38 +252,175 @@ impl Results {
         }
     }
 
+    /**
+     * Includes everything included in another Results for the same case, e.g. from
+     * another thread's part of the search.
+     */
+    pub fn merge(&mut self, other: Results) {
+	for ((_subcase, extrema), (_other_subcase, other_extrema))
+	    in self.subcases.iter_mut().zip(other.subcases) {
+		extrema.merge(other_extrema);
+	    }
+	self.default_subcase.merge(other.default_subcase);
+    }
+
+    /**
+     * A document for each subcase, followed by one for the default subcase, which
+     * has no restrictions of its own.
+     */
+    pub fn documents(&self, bounds: &[Interval]) -> Vec<SubcaseDocument> {
+	let default = (vec![], &self.default_subcase);
+	let all_subcases = self.subcases.iter()
+	    .map(|(subcase, extrema)| (subcase.restrictions.to_owned(), extrema))
+	    .chain(std::iter::once(default));
+	all_subcases.enumerate().map(|(index, (restrictions, extrema))| SubcaseDocument {
+	    label: self.label(index),
+	    restrictions,
+	    contradiction: extrema.is_contradiction(),
+	    intervals: if extrema.is_contradiction() { vec![] } else { extrema.intervals(bounds) },
+	    sum_lower_bounds: extrema.sum_lower_bounds.iter()
+		.map(|sum| sum.map(|numerator| numerator as f64 / extrema.denominator as f64))
+		.collect(),
+	}).collect()
+    }
+
     fn as_label(index: usize) -> char {
 	char::from_u32(index as u32 + ('A' as u32)).unwrap()
     }
+
+    /**
+     * The name of the subcase at the given index if it has one, and otherwise its
+     * positional letter. The default subcase comes after all of the others.
+     */
+    fn label(&self, index: usize) -> String {
+	match self.subcases.get(index).and_then(|(subcase, _extrema)| subcase.name.as_ref()) {
+	    Some(name) => name.to_owned(),
+	    None => Self::as_label(index).to_string(),
+	}
+    }
         
-    pub fn print(&self, bounds: &Vec<Interval>) {
+    pub fn print(&self, bounds: &Vec<Interval>, precision: Option<usize>) {
 	for (index, (subcase, extrema)) in self.subcases.iter().enumerate() {
             println!();
-            println!("Subcase {}: {:?}:", Self::as_label(index), subcase);
-            extrema.print(bounds);
+	    println!("Subcase {}: {:?}:", self.label(index), subcase.restrictions);
+	    extrema.print(bounds, precision);
         }
         println!();
-        println!("Default subcase (subcase {}):", Self::as_label(self.subcases.len()));
-        self.default_subcase.print(bounds);
+	println!("Default subcase (subcase {}):", self.label(self.subcases.len()));
+	self.default_subcase.print(bounds, precision);
     }
 
     pub fn print_machine(&self, case: &Case) {
 	for (index, (subcase, extrema)) in self.subcases.iter().enumerate() {
             println!();
-            println!("Subcase {}: {:?}:", Self::as_label(index), subcase);
-            extrema.print_machine(case, &subcase);
+	    println!("Subcase {}: {:?}:", self.label(index), subcase.restrictions);
+	    extrema.print_machine(case, &subcase.restrictions, &[]);
         }
         println!();
-        println!("Default subcase (subcase {}):", Self::as_label(self.subcases.len()));
-        self.default_subcase.print_machine(case, &vec![]);
+	println!("Default subcase (subcase {}):", self.label(self.subcases.len()));
+	print!("{}", self.default_machine_text(case));
     }
 
-    pub fn get_max_delta(&self, target: f64, depth: usize) -> f64 {
-	let mut max_delta: f64 = self.default_subcase.get_max_delta(target, depth);
-	for (_subcase, extrema) in self.subcases.iter() {
-	    max_delta = max_delta.max(extrema.get_max_delta(target, depth));
+    /**
+     * The default subcase as print_machine prints it, which splits off the same
+     * subcases again when it is re-run.
+     */
+    pub fn default_machine_text(&self, case: &Case) -> String {
+	let subcases = self.subcases.iter().map(|(subcase, _extrema)| subcase.to_owned())
+	    .collect::<Vec<Subcase>>();
+	self.default_subcase.machine_text(case, &[], &subcases)
+    }
+
+    /**
+     * The results as a table of tab-separated values, for pasting into a
+     * spreadsheet. There is a row for each a_i in each subcase which is not a
+     * contradiction, giving its interval, then its delta for the target of each
+     * ProvesBound in the case, then the smallest value of each sum in the case
+     * over the whole subcase, which is repeated on each of its rows.
+     */
+    pub fn to_tsv(&self, case: &Case) -> String {
+	let targets = case.hypotheses.iter().filter_map(|hypothesis| match hypothesis {
+	    Hypothesis::DeltaBound(target, _delta) => Some(*target),
+	    _ => None,
+	}).collect::<Vec<f64>>();
+	let mut tsv = String::from("subcase\tindex\tlb\tub");
+	for target in targets.iter() {
+	    tsv += &format!("\tdelta({})", target);
+	}
+	for coefs in self.sum_lower_bound_coefs.iter() {
+	    tsv += &format!("\tsum{:?}", coefs);
+	}
+	tsv += "\n";
+	let all_extrema = self.subcases.iter().map(|(_subcase, extrema)| extrema)
+	    .chain(std::iter::once(&self.default_subcase));
+	for (label, extrema) in all_extrema.enumerate() {
+	    if extrema.is_contradiction() {
+		continue;
+	    }
+	    let sums = extrema.sum_lower_bounds.iter()
+		.map(|sum| match sum {
+		    Some(numerator) => (*numerator as f64 / extrema.denominator as f64).to_string(),
+		    None => String::new(),
+		})
+		.collect::<Vec<String>>();
+	    for (index, interval) in extrema.intervals(&case.bounds).into_iter().enumerate() {
+		tsv += &format!("{}\t{}\t{}\t{}", self.label(label), index, interval.lb, interval.ub);
+		for target in targets.iter() {
+		    let near = [(0.0, 1.0), (*target, 1.0), (2.0 * target, 1.0)];
+		    tsv += &format!("\t{}", weighted_delta(&interval, &near, 0.0));
+		}
+		for sum in sums.iter() {
+		    tsv += &format!("\t{}", sum);
+		}
+		tsv += "\n";
+	    }
+	}
+	tsv
+    }
+
+    /**
+     * The interval found for each a_i in each subcase which is not a contradiction,
+     * as (label of the subcase, i, interval).
+     */
+    pub fn labelled_intervals(&self, bounds: &[Interval]) -> Vec<(String, usize, Interval)> {
+	let all_extrema = self.subcases.iter().map(|(_subcase, extrema)| extrema)
+	    .chain(std::iter::once(&self.default_subcase));
+	let mut intervals = vec![];
+	for (label, extrema) in all_extrema.enumerate() {
+	    if !extrema.is_contradiction() {
+		for (index, interval) in extrema.intervals(bounds).into_iter().enumerate() {
+		    intervals.push((self.label(label), index, interval));
+		}
+	    }
+	}
+	intervals
+    }
+
+    /**
+     * Returns the worst delta over all subcases, along with the index and
+     * interval responsible for it. Subcases which are contradictions have no
+     * sequences in them, so are skipped; if every one is, then delta is 0.
+     */
+    pub fn get_max_delta(&self, target: f64, depth: usize) -> (f64, usize, Interval) {
+	self.get_max_weighted_delta(&[(0.0, 1.0), (target, 1.0), (2.0 * target, 1.0)], 0.0, depth)
+    }
+
+    /**
+     * As get_max_delta, but weighted as in Extrema::get_max_weighted_delta, over
+     * all non-contradictory subcases.
+     */
+    pub fn get_max_weighted_delta(&self, targets: &[(f64, f64)], error: f64,
+				  depth: usize) -> (f64, usize, Interval) {
+	let all_extrema = self.subcases.iter().map(|(_subcase, extrema)| extrema)
+	    .chain(std::iter::once(&self.default_subcase));
+	let mut worst = (0.0, 0, Interval::UNIT);
+	for extrema in all_extrema.filter(|extrema| !extrema.is_contradiction()) {
+	    let delta = extrema.get_max_weighted_delta(targets, error, depth);
+	    if delta.0 > worst.0 {
+		worst = delta;
+	    }
 	}
-	max_delta
+	worst
     }
 
     /**
@@ -265,3 +470,50 @@ impl Results {
 	is_contradiction
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_delta_reports_the_dominant_index() {
+	let mut extrema = Extrema::new(100, 3, 0);
+	assert!(extrema.is_contradiction());
+	let seqs = [Seq { numerators: vec![40, 30, 10], denominator: 100 },
+		    Seq { numerators: vec![41, 5, 11], denominator: 100 }];
+	for seq in seqs.iter() {
+	    extrema.include_seq(seq, &vec![], 3);
+	}
+	assert!(!extrema.is_contradiction());
+	let targets = [(0.0, 1.0), (0.2, 1.0), (0.4, 1.0)];
+	let (delta, index, interval) = extrema.get_max_weighted_delta(&targets, 0.0, 3);
+	assert_eq!(index, 1);
+	assert!((delta - 0.15).abs() < 1e-9);
+	assert!((interval.lb - 0.05).abs() < 1e-9 && (interval.ub - 0.31).abs() < 1e-9);
+    }
+
+    #[test]
+    fn each_target_has_its_own_tolerance() {
+	let mut extrema = Extrema::new(1000, 3, 0);
+	// So a_0 is in [0.496, 0.504], a_1 in [0.3, 0.36] and a_2 in [0, 0.008].
+	for seq in [Seq { numerators: vec![496, 300, 0], denominator: 1000 },
+		    Seq { numerators: vec![503, 359, 7], denominator: 1000 }] {
+	    extrema.include_seq(&seq, &vec![], 3);
+	}
+	let third = 1.0 / 3.0;
+	let (ratio, index, _) = extrema.get_max_weighted_delta(
+	    &[(0.0, 0.01), (0.5, 0.01), (third, 0.05)], 0.0, 3);
+	assert_eq!(index, 2);
+	assert!((ratio - 0.8).abs() < 1e-9);
+	// With the tolerance near 1/3 as tight as the others, a_1 is too far away.
+	let (ratio, index, _) = extrema.get_max_weighted_delta(
+	    &[(0.0, 0.01), (0.5, 0.01), (third, 0.01)], 0.0, 3);
+	assert_eq!(index, 1);
+	assert!((ratio - (third - 0.3) / 0.01).abs() < 1e-9);
+	// The error is added to each distance before dividing by the tolerance.
+	let (ratio, index, _) = extrema.get_max_weighted_delta(
+	    &[(0.0, 0.01), (0.5, 0.01), (third, 0.05)], 0.002, 3);
+	assert_eq!(index, 2);
+	assert!((ratio - 1.0).abs() < 1e-9);
+    }
+}
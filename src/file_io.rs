@@ -1,4 +1,4 @@
-use std::{fs::{self, File}, path::PathBuf, io::{BufWriter, Write}};
+use std::{fs::{self, File}, panic, path::{Path, PathBuf}, io::{BufWriter, Write}};
 
 use crate::prawitz::*;
 use crate::util::*;
@@ -9,130 +9,892 @@ use crate::case::*;
  * This file deals with all of the reading from, and writing to files.
  */
 
+/**
+ * The project root is the nearest ancestor of the executable called
+ * rademacher-prod. If there is no such directory (e.g. the project was checked
+ * out under a different name) we fall back to the current working directory.
+ */
 fn get_root() -> PathBuf {
+    #[cfg(test)]
+    if let Some(root) = tests::TEST_ROOT.with(|root| root.borrow().clone()) {
+	return root;
+    }
     let mut pathbuf = std::env::current_exe().unwrap();
     pathbuf.pop();
     while !pathbuf.ends_with("rademacher-prod/") {
-        pathbuf.pop();
+	if !pathbuf.pop() {
+	    return std::env::current_dir().unwrap();
+	}
     }
     pathbuf
 }
 
 /**
- * Constructs a Case structure from a file containing all of the relevant information
+ * A case may be referred to as name:index, meaning the case with that index in a
+ * file containing several cases separated by lines of ---. Just name means the
+ * first (or only) case in the file.
  */
-pub fn get_case(filename: &String) -> Option<Case> {
+fn split_case_name(name: &str) -> Option<(&str, usize)> {
+    match name.split_once(':') {
+	Some((filename, index)) => index.trim().parse().ok().map(|index| (filename.trim(), index)),
+	None => Some((name.trim(), 0)),
+    }
+}
+
+/**
+ * The location at which we expect to find the file for the given case.
+ */
+pub fn case_path(name: &str) -> PathBuf {
+    let filename = name.split(':').next().unwrap().trim();
     let mut pathbuf = get_root();
     pathbuf.push(format!("cases/{}.txt", filename));
-    match fs::read_to_string(pathbuf) {
-        Ok(contents) => {
-            let mut lines = contents.trim().lines();
-            let pars = lines.nth(0).unwrap().split(',').collect::<Vec<&str>>();
-            let threshold = pars[0].trim().parse().unwrap();
-	    let prob_cutoff = pars[1].trim().parse().unwrap();
-	    let max_depth = pars[2].trim().parse().unwrap();
-	    let denominator = pars[3].trim().parse().unwrap();
-
-	    let mut bounds_list: Vec<(usize, Interval)> = vec![];
-	    let mut restrictions = vec![];
-	    let mut subcases = vec![];
-	    let mut num_bounds = 0;
-	    let mut hypotheses = vec![];
-	    
-	    use Hypothesis::*;
-            for line in lines {
-		let (func, args) = parse_function_like(line);
-		match func.trim().to_lowercase().as_str() {
-		    "bounds" => {
-			let index: usize = args[0].trim().parse().unwrap();
-			let interval = Interval {
-			    lb: args[1].trim().parse().unwrap(),
-			    ub: args[2].trim().parse().unwrap(),
-			};
+    pathbuf
+}
+
+/**
+ * Splits the contents of a case file into the non-empty lines of each of the
+ * cases it contains.
+ */
+fn split_segments(contents: &str) -> Vec<Vec<&str>> {
+    let mut segments = vec![vec![]];
+    for line in contents.lines() {
+	if line.trim() == "---" {
+	    segments.push(vec![]);
+	} else if !line.trim().is_empty() {
+	    segments.last_mut().unwrap().push(line);
+	}
+    }
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+/**
+ * Everything in a case file other than its first line.
+ */
+struct CaseBody {
+    bounds: Vec<Interval>,
+    restrictions: Vec<Restriction>,
+    subcases: Vec<Subcase>,
+    hypotheses: Vec<Hypothesis>,
+    target: Option<(f64, f64)>,
+    percentile_cutoff: Option<f64>,
+    use_bernstein: bool,
+    tags: Vec<String>,
+}
+
+/**
+ * Parses an inequality of the form Pr[X >= s] >= p into (s, p). We also accept
+ * Pr[X > s], as the simulation in fact bounds this smaller probability.
+ */
+fn parse_target(text: &str) -> Option<(f64, f64)> {
+    let text = text.replace(' ', "");
+    let inner = text.strip_prefix("Pr[X")?;
+    let inner = inner.strip_prefix(">=").or_else(|| inner.strip_prefix('>'))?;
+    let (threshold, prob_cutoff) = inner.split_once("]>=")?;
+    Some((threshold.parse().ok()?, prob_cutoff.parse().ok()?))
+}
+
+/**
+ * Parses the lines of a case file after the first. The stack contains the names
+ * of the cases currently being read, so that we can detect cycles of Includes.
+ * Bounds given in the file itself take precedence over those which are included.
+ */
+fn parse_case_body<'a>(lines: impl Iterator<Item = &'a str>, stack: &mut Vec<String>) -> CaseBody {
+    let mut bounds_list: Vec<(usize, Interval)> = vec![];
+    let mut inherited_bounds: Vec<Vec<Interval>> = vec![];
+    let mut restrictions = vec![];
+    let mut subcases = vec![];
+    let mut num_bounds = 0;
+    let mut hypotheses = vec![];
+    let mut target = None;
+    let mut percentile_cutoff = None;
+    let mut use_bernstein = true;
+    let mut tags = vec![];
+
+    use Hypothesis::*;
+    for line in lines {
+	let (func, args) = parse_function_like(line);
+	match canonical_name(func).as_str() {
+	    "subcase" => {
+		// An optional first argument name=... names the subcase.
+		let name = args.first().and_then(|x| x.strip_prefix("name="))
+		    .map(|name| name.trim().to_owned());
+		let restrictions = args.iter().skip(name.is_some() as usize)
+		    .map(|x| Restriction::of_string(x))
+		    .collect::<Vec<Restriction>>();
+		subcases.push(Subcase { name, restrictions });
+	    }
+	    "include" => {
+		let name = args[0].to_owned();
+		let (filename, index) = split_case_name(&name)
+		    .unwrap_or_else(|| panic!("Could not parse included case {}!", name));
+		let key = format!("{}:{}", filename, index);
+		if stack.contains(&key) {
+		    panic!("Cycle of Includes found at case {}!", name);
+		}
+		let contents = fs::read_to_string(case_path(&name))
+		    .unwrap_or_else(|_| panic!("Included case {} not found!", name));
+		let segments = split_segments(&contents);
+		let segment = segments.get(index)
+		    .unwrap_or_else(|| panic!("Included case {} not found!", name));
+		stack.push(key);
+		let base = parse_case_body(segment.iter().skip(1).copied(), stack);
+		stack.pop();
+		num_bounds = num_bounds.max(base.bounds.len());
+		inherited_bounds.push(base.bounds);
+		restrictions.extend(base.restrictions);
+		subcases.extend(base.subcases);
+	    }
+	    "usebernstein" => {
+		use_bernstein = match args[..] {
+		    [flag] => flag.trim().parse()
+			.unwrap_or_else(|_| panic!("Expected format: UseBernstein(true|false)")),
+		    _ => panic!("Expected format: UseBernstein(true|false)"),
+		};
+	    }
+	    "tags" => {
+		tags.extend(args.iter().map(|x| x.trim().to_owned()).filter(|x| !x.is_empty()));
+	    }
+	    "prove" => {
+		let prove_target = parse_target(args[0])
+		    .unwrap_or_else(|| panic!("Expected format: Prove(Pr[X >= s] >= p)"));
+		if target.is_some_and(|target| target != prove_target) {
+		    panic!("Prove gives {:?}, but Percentile gives {:?}!", prove_target,
+			   target.unwrap());
+		}
+		target = Some(prove_target);
+	    }
+	    "percentile" => {
+		// Percentile(s, q) means Pr[X >= s] >= q / 100, i.e. s is at most the
+		// (100 - q)th percentile of X. As X is symmetric, q can be at most 50.
+		// Percentile(q) takes s from the first line, and replaces the p given
+		// there, as otherwise the first line and Percentile would conflict.
+		let (threshold, percent): (Option<f64>, f64) = match args[..] {
+		    [threshold, percent] => (Some(threshold.parse().unwrap()), percent.parse().unwrap()),
+		    [percent] => (None, percent.parse().unwrap()),
+		    _ => panic!("Expected format: Percentile(q) or Percentile(s, q)"),
+		};
+		if percent <= 0.0 || percent > 50.0 {
+		    panic!("Percentile needs 0 < q <= 50, but q = {}!", percent);
+		}
+		match threshold {
+		    None => percentile_cutoff = Some(percent / 100.0),
+		    Some(threshold) => {
+			let percentile_target = (threshold, percent / 100.0);
+			if target.is_some_and(|target| target != percentile_target) {
+			    panic!("Percentile gives {:?}, but Prove gives {:?}!", percentile_target,
+				   target.unwrap());
+			}
+			target = Some(percentile_target);
+		    }
+		}
+	    }
+	    "provesbound" if args.len() == 1 => {
+		let targets = split_list(args[0].trim().trim_start_matches('[').trim_end_matches(']'))
+		    .iter()
+		    .map(|term| {
+			let pair = split_list(term.trim_start_matches('('));
+			if pair.len() != 2 {
+			    panic!("Expected each term of {} to be (target, tolerance)!", line);
+			}
+			let tolerance: f64 = pair[1].trim().parse().unwrap();
+			if tolerance <= 0.0 {
+			    panic!("Tolerances in {} must be positive!", line);
+			}
+			(pair[0].trim().parse().unwrap(), tolerance)
+		    })
+		    .collect();
+		hypotheses.push(WeightedDeltaBound(targets));
+	    }
+	    "provesbound" => {
+		let target = args[0].trim().parse().unwrap();
+		let delta = args[1].trim().parse().unwrap();
+		hypotheses.push(DeltaBound(target, delta));
+	    }
+	    "contradiction" => {
+		hypotheses.push(Contradiction);
+	    }
+	    "provessumlowerbound" => {
+		let coefs = split_list(args[0].split_once('(').unwrap().1)
+		    .iter()
+		    .map(|x| x.trim().parse().unwrap())
+		    .collect::<Vec<i32>>();
+		let bound = args[1].trim().parse().unwrap();
+		hypotheses.push(SumLowerBound(coefs, bound));
+	    }
+	    &_ => {
+		let restriction = Restriction::of_string(line);
+		if let Restriction::Bounds(_, interval) | Restriction::RangeBounds(_, _, interval)
+		    = restriction {
+			check_bounds_interval(&interval, line);
+		    }
+		match restriction {
+		    Restriction::Bounds(index, interval) => {
 			bounds_list.push((index, interval));
 			if index >= num_bounds {
 			    num_bounds = index + 1;
 			}
 		    }
-		    "subcase" => {
-			let restrictions = args.iter()
-			    .map(|x| Restriction::of_string(*x))
-			    .collect::<Vec<Restriction>>();
-			subcases.push(restrictions);
-		    }
-		    "provesbound" => {
-			let target = args[0].trim().parse().unwrap();
-			let delta = args[1].trim().parse().unwrap();
-			hypotheses.push(DeltaBound(target, delta));
+		    // This is just shorthand for a Bounds for each index in the range.
+		    Restriction::RangeBounds(start, end, interval) => {
+			bounds_list.extend((start..end).map(|index| (index, interval)));
+			num_bounds = num_bounds.max(end);
 		    }
-		    "contradiction" => {
-			hypotheses.push(Contradiction);
-		    }
-		    "provessumlowerbound" => {
-			let coefs = split_list(args[0].split_once('(').unwrap().1)
-			    .iter()
-			    .map(|x| x.trim().parse().unwrap())
-			    .collect::<Vec<i32>>();
-			let bound = args[1].trim().parse().unwrap();
-			hypotheses.push(SumLowerBound(coefs, bound));
-		    }
-		    &_ => {
-			let restriction = Restriction::of_string(line);
-			if let Restriction::Bounds(index, interval) = restriction {
-			    bounds_list.push((index, interval));
-			    if index >= num_bounds {
-				num_bounds = index + 1;
-			    }
-			} else {
-			    restrictions.push(restriction);
-			}
-		    }
-			
+		    _ => restrictions.push(restriction),
 		}
-            }
+	    }
+	}
+    }
 
-	    let mut bounds = vec![Interval::UNIT; num_bounds];
+    let mut bounds = vec![Interval::UNIT; num_bounds];
+    let mut is_own_bound = vec![false; num_bounds];
 
-	    for (index, interval) in bounds_list.iter() {
-		bounds[*index].intersect_inplace(interval);
+    for (index, interval) in bounds_list.iter() {
+	bounds[*index].intersect_inplace(interval);
+	is_own_bound[*index] = true;
+    }
+    for base_bounds in inherited_bounds.iter() {
+	for (index, interval) in base_bounds.iter().enumerate() {
+	    if !is_own_bound[index] {
+		bounds[index].intersect_inplace(interval);
 	    }
-	    
-            Some(Case { threshold, prob_cutoff, max_depth, denominator, bounds,
-	       restrictions, subcases, hypotheses })
+	}
+    }
+
+    CaseBody { bounds, restrictions, subcases, hypotheses, target, percentile_cutoff,
+	       use_bernstein, tags }
+}
+
+/**
+ * Checks the interval given in a Bounds or RangeBounds line. An empty interval
+ * is surely a mistake, as the case could then be proved trivially, and one
+ * reaching outside [0, 1] probably is, as every a_i lies in [0, 1] anyway.
+ */
+fn check_bounds_interval(interval: &Interval, line: &str) {
+    if interval.is_empty() {
+	panic!("The interval in {} is empty, with width {}!", line.trim(), interval.width());
+    }
+    if !Interval::UNIT.contains(interval.lb) || !Interval::UNIT.contains(interval.ub) {
+	println!("WARNING: the interval in {} reaches outside [0, 1], where every a_i lies.",
+		 line.trim());
+    }
+}
+
+/**
+ * Constructs a Case structure from the lines of a single case, the first of
+ * which contains the parameters. Here key identifies the case, for Includes.
+ * The first line is either s, p, k, d or just k, d, in which case s and p are
+ * taken from a line Prove(Pr[X >= s] >= p). A line Percentile(q) replaces the p
+ * on the first line with q / 100.
+ */
+fn parse_case(lines: &[&str], key: String) -> Case {
+    let pars = lines[0].split(',').collect::<Vec<&str>>();
+    let (explicit_target, pars) = match pars.len() {
+	2 => (None, pars),
+	4 => (Some((pars[0].trim().parse::<f64>().unwrap(),
+		    pars[1].trim().parse::<f64>().unwrap())), pars[2..].to_vec()),
+	_ => panic!("Expected the first line of case {} to be s, p, k, d or k, d!", key),
+    };
+    let max_depth = pars[0].trim().parse().unwrap();
+    let denominator = pars[1].trim().parse().unwrap();
+    if !square_sums_fit(denominator, max_depth) {
+	panic!("The denominator {} is too large for depth {}: sums of squares of numerators would overflow!",
+	       denominator, max_depth);
+    }
+    if denominator > MAX_EXACT_DENOMINATOR {
+	println!("WARNING: the denominator {} exceeds 2^53, so numerators will not be exactly representable as floats!",
+		 denominator);
+    }
+
+    let CaseBody { bounds, restrictions, subcases, hypotheses, target, percentile_cutoff,
+		   use_bernstein, tags } =
+	parse_case_body(lines.iter().skip(1).copied(), &mut vec![key.clone()]);
+    let explicit_target = match (explicit_target, percentile_cutoff) {
+	(Some((threshold, _prob_cutoff)), Some(percentile_cutoff)) =>
+	    Some((threshold, percentile_cutoff)),
+	(None, Some(_percentile_cutoff)) =>
+	    panic!("Percentile(q) in case {} takes s from the first line, so it must be s, p, k, d!", key),
+	(explicit_target, None) => explicit_target,
+    };
+    let (threshold, prob_cutoff) = match (explicit_target, target) {
+	(Some(explicit), Some(target)) if explicit != target => {
+	    panic!("Case {} has s, p = {:?} but Prove gives {:?}!", key, explicit, target)
+	}
+	(Some(explicit), _) => explicit,
+	(None, Some(target)) => target,
+	(None, None) => panic!("Case {} needs either s, p on its first line or a Prove line!", key),
+    };
+    // Every probability is at least 0, so every sequence would be resolved and
+    // every hypothesis proved vacuously.
+    if prob_cutoff <= 0.0 {
+	panic!("Case {} has p = {}, but p must be positive!", key, prob_cutoff);
+    }
+
+    let case = Case { threshold, prob_cutoff, max_depth, denominator, bounds,
+		      restrictions, subcases, hypotheses, resolve_epsilon: RESOLVE_EPSILON,
+		      method: BoundMethod::Full, use_bernstein, tags };
+    if case.cannot_be_proved() {
+	println!("WARNING: case {} has s = {} > 0 and p = {} >= 1/2, but P[X >= s] <= 1/2, so nothing can be proved!",
+		 key, threshold, prob_cutoff);
+    }
+    case.warn_empty_numerator_ranges(&key);
+    case
+}
+
+/**
+ * Every case in every file in cases/, in the order of list_cases, each with its
+ * name in the form used by get_case. Returns None if there is no cases/ directory.
+ */
+pub fn all_cases() -> Option<Vec<(String, Case)>> {
+    let mut all = vec![];
+    for (filename, _headers) in list_cases()? {
+	let cases = get_cases(&filename).unwrap_or_default();
+	let several = cases.len() > 1;
+	for (index, case) in cases.into_iter().enumerate() {
+	    let name = if several {
+		format!("{}:{}", filename, index)
+	    } else {
+		filename.to_owned()
+	    };
+	    all.push((name, case));
+	}
+    }
+    Some(all)
+}
+
+/**
+ * Lists the names of all of the files in cases/, in alphabetical order, each
+ * with the first line of every case in that file. Returns None if there is no
+ * cases/ directory.
+ */
+pub fn list_cases() -> Option<Vec<(String, Vec<String>)>> {
+    let mut pathbuf = get_root();
+    pathbuf.push("cases");
+    let mut cases = vec![];
+    for entry in fs::read_dir(pathbuf).ok()?.flatten() {
+	let path = entry.path();
+	if path.extension().is_some_and(|extension| extension == "txt") {
+	    let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+	    let headers = match fs::read_to_string(&path) {
+		Ok(contents) => split_segments(&contents).iter()
+		    .map(|lines| lines[0].trim().to_owned())
+		    .collect(),
+		Err(_e) => vec![],
+	    };
+	    cases.push((name, headers));
+	}
+    }
+    cases.sort();
+    Some(cases)
+}
+
+/**
+ * Constructs every Case in a file, in order.
+ */
+pub fn get_cases(filename: &str) -> Option<Vec<Case>> {
+    match fs::read_to_string(case_path(filename)) {
+	Ok(contents) => {
+	    Some(split_segments(&contents).iter().enumerate()
+		 .map(|(index, lines)| parse_case(lines, format!("{}:{}", filename, index)))
+		 .collect())
         }
         Err(_e) => None
     }
 }
 
-pub fn bounder_to_file(bounder: &Bounder) {
-    println!("  WRITING BOUNDER! ");
+/**
+ * The names of all of the cases in cases/ with the given tag, in alphabetical
+ * order of file, in the form used by get_case. A file which cannot be parsed is
+ * skipped with a warning, rather than stopping the whole scan. Returns None if
+ * there is no cases/ directory.
+ */
+pub fn cases_with_tag(tag: &str) -> Option<Vec<String>> {
+    let mut names = vec![];
+    for (filename, _headers) in list_cases()? {
+	let cases = match panic::catch_unwind(|| get_cases(&filename)) {
+	    Ok(cases) => cases.unwrap_or_default(),
+	    Err(_) => {
+		println!("WARNING: skipping {}, which could not be parsed.", filename);
+		continue;
+	    }
+	};
+	let several = cases.len() > 1;
+	for (index, case) in cases.iter().enumerate() {
+	    if case.tags.iter().any(|x| x == tag) {
+		names.push(if several { format!("{}:{}", filename, index) } else { filename.clone() });
+	    }
+	}
+    }
+    Some(names)
+}
+
+/**
+ * Constructs a Case structure from a file containing all of the relevant information
+ */
+pub fn get_case(name: &str) -> Option<Case> {
+    let (filename, index) = split_case_name(name)?;
+    get_cases(filename)?.into_iter().nth(index)
+}
+
+/**
+ * An entry of the table as written to a file, with 17 significant digits, which is
+ * always enough to parse back to exactly the same value. So the file does not
+ * depend on how the shortest representation is found.
+ */
+fn format_double(x: f64) -> String {
+    format!("{:.16e}", x)
+}
+
+/**
+ * As format_double, for an entry stored as f32, which needs 9 significant digits.
+ */
+fn format_single(x: f32) -> String {
+    format!("{:.8e}", x)
+}
+
+fn write_bounder(bounder: &Bounder, filename: &str) {
     let mut pathbuf = get_root();
-    pathbuf.push(format!("bounder.csv"));
+    pathbuf.push(filename);
     let mut writer = BufWriter::new(File::create(pathbuf).unwrap());
     let _ = writer.write(bounder.header_line().as_bytes());
     let _ = writer.write("\n".as_bytes());
-    for row in bounder.bounds().iter() {
-        let _ = writer.write(row.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",").as_bytes());
+    let rows = match bounder.bounds() {
+	Entries::Double(bounds) => bounds.iter()
+	    .map(|row| row.iter().map(|x| format_double(*x)).collect::<Vec<String>>())
+	    .collect::<Vec<_>>(),
+	Entries::Single(bounds) => bounds.iter()
+	    .map(|row| row.iter().map(|x| format_single(*x)).collect::<Vec<String>>())
+	    .collect::<Vec<_>>(),
+    };
+    for row in rows {
+	let _ = writer.write(row.join(",").as_bytes());
         let _ = writer.write("\n".as_bytes());
     }
 }
 
-pub fn bounder_from_file() -> Bounder {
+/**
+ * Returns None if the file does not exist, so that the user has a chance to
+ * run generate rather than the program crashing.
+ */
+fn read_bounder(filename: &str) -> Option<Bounder> {
     let mut pathbuf = get_root();
-    pathbuf.push(format!("bounder.csv"));
-    let contents = fs::read_to_string(pathbuf).unwrap();
+    pathbuf.push(filename);
+    let contents = fs::read_to_string(pathbuf).ok()?;
     let mut lines = contents.trim().lines();
     let first_pars = lines.nth(0).unwrap().split(',').collect::<Vec<&str>>();
-    let mut bounds = vec![];
+    // The optional fourth field says that the entries are stored as f32.
+    let bounds = if first_pars.get(3).map(|x| x.trim()) == Some("f32") {
+	Entries::Single(lines.map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+			.collect())
+    } else {
+	Entries::Double(lines.map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+			.collect())
+    };
+    let coef_granularity: usize = first_pars[0].parse().unwrap();
+    let thresh_granularity = first_pars[1].parse().unwrap();
+    let max_bound: usize = first_pars[2].parse().unwrap();
 
-    for line in lines {
-        bounds.push(line.split(',').map(|x| x.parse().unwrap()).collect::<Vec<f64>>());
+    // Lookups trust the header, so a truncated file would silently give wrong bounds.
+    if bounds.num_rows() != coef_granularity {
+	panic!("The header of {} gives {} rows, but the file has {}!",
+	       filename, coef_granularity, bounds.num_rows());
+    }
+    if let Some(a) = (0..bounds.num_rows()).find(|a| bounds.row_len(*a) != 2 * max_bound) {
+	panic!("The header of {} gives rows of {} entries, but row {} has {}!",
+	       filename, 2 * max_bound, a, bounds.row_len(a));
+    }
+
+    Some(Bounder::new_manual(bounds, coef_granularity, thresh_granularity, max_bound))
+}
+
+pub fn bounder_to_file(bounder: &Bounder) {
+    println!("  WRITING BOUNDER! ");
+    write_bounder(bounder, "bounder.csv");
+}
+
+/**
+ * The table part way through precomputation #2, after the given number of
+ * iterations, for studying how it converges.
+ */
+pub fn bounder_snapshot_to_file(bounder: &Bounder, iteration: usize) {
+    write_bounder(bounder, &format!("bounder_iter_{}.csv", iteration));
+}
+
+/**
+ * Baselines and JSON documents of runs are saved relative to the project root,
+ * like the tables of bounds. Returns None if there is no such file.
+ */
+pub fn text_from_file(filename: &str) -> Option<String> {
+    let mut pathbuf = get_root();
+    pathbuf.push(filename);
+    fs::read_to_string(pathbuf).ok()
+}
+
+pub fn text_to_file(filename: &str, text: &str) {
+    let mut pathbuf = get_root();
+    pathbuf.push(filename);
+    fs::write(pathbuf, text).unwrap();
+}
+
+/**
+ * Reads a table of bounds from the given file, relative to the project root, e.g.
+ * one saved from an earlier run of generate.
+ */
+pub fn bounder_from_named_file(filename: &str) -> Option<Bounder> {
+    read_bounder(filename)
+}
+
+pub fn bounder_from_file() -> Option<Bounder> {
+    read_bounder("bounder.csv")
+}
+
+/**
+ * The table as it stands after precomputation #1 only, so that precomputation #2
+ * can be re-run with a different number of iterations without redoing #1.
+ */
+pub fn base_bounder_to_file(bounder: &Bounder) {
+    println!("  WRITING BASE BOUNDER! ");
+    write_bounder(bounder, "bounder_base.csv");
+}
+
+pub fn base_bounder_from_file() -> Option<Bounder> {
+    read_bounder("bounder_base.csv")
+}
+
+/**
+ * Maps a value in [0, 1] to a colour, interpolating linearly between a handful of
+ * points of the viridis colour map (dark purple for 0, yellow for 1).
+ */
+fn colour_of(value: f64) -> [u8; 3] {
+    const STOPS: [[f64; 3]; 5] = [
+	[68.0, 1.0, 84.0],
+	[59.0, 82.0, 139.0],
+	[33.0, 145.0, 140.0],
+	[94.0, 201.0, 98.0],
+	[253.0, 231.0, 37.0],
+    ];
+    let scaled = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (scaled.floor() as usize).min(STOPS.len() - 2);
+    let t = scaled - index as f64;
+    let mut colour = [0; 3];
+    for (channel, entry) in colour.iter_mut().enumerate() {
+	let mixed = STOPS[index][channel] * (1.0 - t) + STOPS[index + 1][channel] * t;
+	*entry = mixed.round() as u8;
+    }
+    colour
+}
+
+/**
+ * Renders the table of bounds as a PNG heatmap, with one pixel per entry. The
+ * coefficient a increases from top to bottom and the cutoff x from left to right.
+ * Since the image itself carries no labels, the ranges of a and x are printed.
+ */
+pub fn bounder_to_heatmap(bounder: &Bounder, path: &Path) -> Result<(), png::EncodingError> {
+    let bounds = bounder.bounds();
+    let height = bounds.num_rows();
+    let width = bounds.row_len(0);
+    let mut data = Vec::with_capacity(3 * width * height);
+    for a in 0..height {
+	for y in 0..bounds.row_len(a) {
+	    data.extend_from_slice(&colour_of(bounds.entry(a, y)));
+	}
+    }
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?),
+					width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+    let (min_cutoff, max_cutoff) = bounder.threshold_range();
+    println!("Wrote {}x{} heatmap to {}", width, height, path.display());
+    // There is one row per step of a, starting from zero.
+    println!("Rows: a from 0 (top) to {} (bottom). Columns: x from {} (left) to {} (right).",
+	     (height - 1) as f64 / height as f64, min_cutoff, max_cutoff);
+    println!("Colour: D(a, x) from 0 (purple) to 1 (yellow).");
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    thread_local! {
+	// Each test works in its own temporary directory in place of the project root.
+	pub static TEST_ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    }
+
+    /**
+     * Makes an empty temporary directory the project root for the current thread,
+     * so that tests running at the same time do not see each other's files.
+     */
+    pub fn use_temp_root(name: &str) -> PathBuf {
+	let root = std::env::temp_dir()
+	    .join(format!("rademacher-test-{}-{}", std::process::id(), name));
+	let _ = fs::remove_dir_all(&root);
+	fs::create_dir_all(root.join("cases")).unwrap();
+	TEST_ROOT.with(|test_root| *test_root.borrow_mut() = Some(root.clone()));
+	root
+    }
+
+    pub fn write_case_file(name: &str, contents: &str) {
+	fs::write(case_path(name), contents).unwrap();
+    }
+
+    #[test]
+    fn missing_files_give_none() {
+	let root = use_temp_root("missing_files");
+	assert!(get_case("nonexistent").is_none());
+	assert!(bounder_from_file().is_none());
+	assert!(base_bounder_from_file().is_none());
+	assert_eq!(list_cases(), Some(vec![]));
+	fs::remove_dir_all(root.join("cases")).unwrap();
+	assert_eq!(list_cases(), None);
+	assert_eq!(cases_with_tag("anything"), None);
+    }
+
+    #[test]
+    fn included_bounds_can_be_overridden() {
+	use_temp_root("include");
+	write_case_file("parent", "0.5, 0.3, 4, 100\nBounds(0, 0.5, 1)\nBounds(1, 0.2, 0.4)\n\
+				   MidSumUpperBound(0, 1, 1.5)\n");
+	write_case_file("child", "0.5, 0.3, 4, 100\nInclude(parent)\nBounds(1, 0.1, 0.3)\n");
+	let case = get_case("child").unwrap();
+	assert_eq!(format!("{:?}", case.bounds), "[0.5, 1, 0.1, 0.3]");
+	assert_eq!(format!("{:?}", case.restrictions), "[MidSumUpperBound(0, 1, 1.5)]");
     }
 
-    Bounder::new_manual(
-        bounds,
-        first_pars[0].parse().unwrap(),
-        first_pars[1].parse().unwrap(),
-        first_pars[2].parse().unwrap(),
-    )
+    #[test]
+    fn every_segment_of_a_file_is_a_case() {
+	use_temp_root("segments");
+	write_case_file("several", "0.5, 0.3, 4, 100\nBounds(0, 0.5, 1)\n---\n\n\
+				    3, 1000\nProve(Pr[X >= 0.4] >= 0.25)\nZeroAfter(2)\n---\n\
+				    0.2, 0.1, 2, 10\n");
+	let cases = get_cases("several").unwrap();
+	assert_eq!(cases.len(), 3);
+	let summary = cases.iter()
+	    .map(|case| (case.threshold, case.prob_cutoff, case.max_depth, case.denominator))
+	    .collect::<Vec<_>>();
+	assert_eq!(summary, vec![(0.5, 0.3, 4, 100), (0.4, 0.25, 3, 1000), (0.2, 0.1, 2, 10)]);
+	assert_eq!(format!("{:?}", cases[0].bounds), "[0.5, 1]");
+	assert_eq!(format!("{:?}", cases[1].restrictions), "[ZeroAfter(2)]");
+	assert_eq!(get_case("several:1").unwrap().denominator, 1000);
+	assert!(get_case("several:3").is_none());
+    }
+
+    #[test]
+    fn heatmap_has_a_pixel_per_entry() {
+	let root = use_temp_root("heatmap");
+	let bounds = vec![vec![1.0, 0.75, 0.5, 0.25, 0.0]; 3];
+	let bounder = Bounder::new_manual(Entries::Double(bounds), 3, 1, 2);
+	let path = root.join("heatmap.png");
+	bounder_to_heatmap(&bounder, &path).unwrap();
+	let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+	let mut reader = decoder.read_info().unwrap();
+	let mut data = vec![0; reader.output_buffer_size()];
+	let info = reader.next_frame(&mut data).unwrap();
+	assert_eq!((info.width, info.height), (5, 3));
+	assert_eq!(info.color_type, png::ColorType::Rgb);
+	assert!(data.iter().any(|byte| *byte != 0));
+    }
+
+    #[test]
+    fn prove_matches_the_explicit_fields() {
+	use_temp_root("prove");
+	let body = "Bounds(0, 0.3, 0.9)\nZeroAfter(2)\nContradiction\n";
+	write_case_file("explicit", &format!("0.4, 0.25, 3, 1000\n{}", body));
+	write_case_file("high_level", &format!("3, 1000\nProve(Pr[X >= 0.4] >= 0.25)\n{}", body));
+	write_case_file("both", &format!("0.4, 0.25, 3, 1000\nProve(Pr[X >= 0.4] >= 0.25)\n{}", body));
+	let summary = |case: Case| (case.threshold, case.prob_cutoff, case.max_depth, case.denominator,
+				    format!("{:?} {:?} {:?}", case.bounds, case.restrictions,
+					    case.hypotheses));
+	let explicit = summary(get_case("explicit").unwrap());
+	assert_eq!(summary(get_case("high_level").unwrap()), explicit);
+	assert_eq!(summary(get_case("both").unwrap()), explicit);
+    }
+
+    #[test]
+    fn use_bernstein_defaults_to_true() {
+	use_temp_root("use_bernstein");
+	write_case_file("default", "0.4, 0.25, 3, 1000\n");
+	write_case_file("off", "0.4, 0.25, 3, 1000\nUseBernstein(false)\n");
+	write_case_file("on", "0.4, 0.25, 3, 1000\nUseBernstein(true)\n");
+	assert!(get_case("default").unwrap().use_bernstein);
+	assert!(!get_case("off").unwrap().use_bernstein);
+	assert!(get_case("on").unwrap().use_bernstein);
+    }
+
+    #[test]
+    fn proves_bound_takes_a_list_of_targets() {
+	use_temp_root("weighted_delta");
+	write_case_file("weighted", "0.4, 0.25, 3, 1000\nProvesBound([(0, 0.01), (0.5, 0.01), (0.333, 0.05)])\n");
+	assert_eq!(format!("{:?}", get_case("weighted").unwrap().hypotheses),
+		   "[WeightedDeltaBound([(0.0, 0.01), (0.5, 0.01), (0.333, 0.05)])]");
+    }
+
+    #[test]
+    fn formatted_entries_parse_back_exactly() {
+	let doubles = [0.0, 1.0, 0.5, 0.1, 1.0 / 3.0, 1.0 - f64::EPSILON, 0.123_456_789_012_345_67,
+		       f64::MIN_POSITIVE, f64::MIN_POSITIVE / 3.0, 5e-324, f64::MAX];
+	for x in doubles.into_iter().chain((1..1000).map(|i| 1.0 / i as f64)) {
+	    let text = format_double(x);
+	    assert_eq!(text.parse::<f64>().unwrap().to_bits(), x.to_bits(), "{} from {}", text, x);
+	}
+	let singles = [0.0, 1.0, 0.1, 1.0 / 3.0, 1.0 - f32::EPSILON, f32::MIN_POSITIVE,
+		       f32::MIN_POSITIVE / 3.0, 1e-45, f32::MAX];
+	for x in singles.into_iter().chain((1..1000).map(|i| 1.0 / i as f32)) {
+	    let text = format_single(x);
+	    assert_eq!(text.parse::<f32>().unwrap().to_bits(), x.to_bits(), "{} from {}", text, x);
+	}
+	// The width is fixed, whatever the value.
+	assert_eq!(format_double(0.5), "5.0000000000000000e-1");
+	assert_eq!(format_single(0.5), "5.00000000e-1");
+    }
+
+    #[test]
+    fn tags_select_exactly_the_tagged_cases() {
+	use_temp_root("tags");
+	write_case_file("a", "0.5, 0.3, 4, 100\nTags(oleszkiewicz, tail)\n");
+	write_case_file("b", "0.5, 0.3, 4, 100\n---\n0.4, 0.3, 4, 100\nTags(tail)\n");
+	write_case_file("c", "0.5, 0.3, 4, 100\nTags( oleszkiewicz )\n");
+	write_case_file("d", "0.5, 0.3, 4, 100\n");
+	// This cannot be parsed, so is skipped rather than ending the scan.
+	write_case_file("e", "0.5, 0.3, 4, 100\nTags(tail)\nNoSuchDirective(1)\n");
+	assert_eq!(cases_with_tag("tail"), Some(vec![String::from("a"), String::from("b:1")]));
+	assert_eq!(cases_with_tag("oleszkiewicz"), Some(vec![String::from("a"), String::from("c")]));
+	assert_eq!(cases_with_tag("Tail"), Some(vec![]));
+	assert_eq!(get_case("b:0").unwrap().tags, Vec::<String>::new());
+    }
+
+    #[test]
+    fn cases_are_listed_with_their_first_lines() {
+	let root = use_temp_root("list");
+	write_case_file("b", "0.5, 0.3, 4, 100\n");
+	write_case_file("a", "3, 1000\nProve(Pr[X >= 0.4] >= 0.25)\n---\n0.2, 0.1, 2, 10\n");
+	fs::write(root.join("cases").join("notes.md"), "not a case").unwrap();
+	let expected = vec![
+	    (String::from("a"), vec![String::from("3, 1000"), String::from("0.2, 0.1, 2, 10")]),
+	    (String::from("b"), vec![String::from("0.5, 0.3, 4, 100")]),
+	];
+	assert_eq!(list_cases(), Some(expected));
+    }
+
+    #[test]
+    #[should_panic(expected = "p must be positive")]
+    fn zero_cutoff_is_refused() {
+	use_temp_root("zero_cutoff");
+	write_case_file("vacuous", "0.5, 0, 4, 100\n");
+	get_case("vacuous");
+    }
+
+    #[test]
+    fn range_bounds_match_explicit_lines() {
+	use_temp_root("range_bounds");
+	write_case_file("range", "0.5, 0.3, 16, 100\nRangeBounds(5, 15, 0, 0.3)\n");
+	let explicit = (5..15).map(|index| format!("Bounds({}, 0, 0.3)\n", index)).collect::<String>();
+	write_case_file("explicit", &format!("0.5, 0.3, 16, 100\n{}", explicit));
+	assert_eq!(format!("{:?}", get_case("range").unwrap().bounds),
+		   format!("{:?}", get_case("explicit").unwrap().bounds));
+    }
+
+    #[test]
+    fn fingerprint_survives_a_round_trip_and_sees_every_cell() {
+	use_temp_root("fingerprint");
+	let bounds = vec![vec![0.9, 0.7, 0.1 + 0.2, 1.0 / 3.0], vec![0.8, 0.6, 0.2, 1e-300]];
+	let bounder = Bounder::new_manual(Entries::Double(bounds.clone()), 2, 1, 2);
+	bounder_to_file(&bounder);
+	assert_eq!(bounder_from_file().unwrap().fingerprint(), bounder.fingerprint());
+	for a in 0..2 {
+	    for y in 0..4 {
+		let mut changed = bounds.clone();
+		changed[a][y] = f64::from_bits(changed[a][y].to_bits() + 1);
+		let changed = Bounder::new_manual(Entries::Double(changed), 2, 1, 2);
+		assert_ne!(changed.fingerprint(), bounder.fingerprint(), "at ({}, {})", a, y);
+	    }
+	}
+	let mut single = Bounder::new_manual(Entries::Double(bounds), 2, 1, 2);
+	single.store_as_single();
+	bounder_to_file(&single);
+	assert_eq!(bounder_from_file().unwrap().fingerprint(), single.fingerprint());
+	assert_ne!(single.fingerprint(), bounder.fingerprint());
+    }
+
+    #[test]
+    fn snapshots_are_written_every_so_many_iterations() {
+	let root = use_temp_root("snapshots");
+	let mut bounder = Bounder::fill_base_sized(DEFAULT_Q, 10, 10);
+	bounder.relax(25, Some(10), false, bounder_snapshot_to_file);
+	let mut snapshots: Vec<String> = fs::read_dir(&root).unwrap()
+	    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+	    .filter(|name| name.starts_with("bounder_iter_"))
+	    .collect();
+	snapshots.sort();
+	assert_eq!(snapshots, ["bounder_iter_10.csv", "bounder_iter_20.csv"]);
+	let snapshot = bounder_from_named_file("bounder_iter_20.csv").unwrap();
+	assert_eq!(snapshot.header_line(), bounder.header_line());
+    }
+
+    #[test]
+    fn named_subcases_keep_their_names() {
+	use_temp_root("named_subcases");
+	write_case_file("named", "0.5, 0.3, 2, 10\nSubcase(name=small_a0, Bounds(0, 0, 0.5))\n\
+				 Subcase(Bounds(0, 0.5, 1))\n");
+	let case = get_case("named").unwrap();
+	let labels = crate::extrema::Results::new(&case).documents(&case.bounds).into_iter()
+	    .map(|document| document.label).collect::<Vec<String>>();
+	assert_eq!(labels, ["small_a0", "B", "C"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The header of bounder.csv gives 3 rows, but the file has 2!")]
+    fn header_over_stating_the_rows_is_caught() {
+	let root = use_temp_root("over_stated_rows");
+	fs::write(root.join("bounder.csv"), "3,1,1\n0.5,0.25\n0.5,0.25\n").unwrap();
+	bounder_from_file();
+    }
+
+    #[test]
+    #[should_panic(expected = "The header of bounder.csv gives rows of 4 entries, but row 1 has 3!")]
+    fn short_row_is_caught() {
+	let root = use_temp_root("short_row");
+	fs::write(root.join("bounder.csv"), "2,1,2\n0.5,0.5,0.25,0\n0.5,0.5,0.25\n").unwrap();
+	bounder_from_file();
+    }
+
+    #[test]
+    fn percentile_sets_the_cutoff() {
+	use_temp_root("percentile");
+	write_case_file("quarter", "3, 1000\nPercentile(1, 25)\n");
+	write_case_file("proved", "3, 1000\nProve(Pr[X >= 1] >= 0.25)\n");
+	let (quarter, proved) = (get_case("quarter").unwrap(), get_case("proved").unwrap());
+	assert_eq!((quarter.threshold, quarter.prob_cutoff), (1.0, 0.25));
+	assert_eq!((quarter.threshold, quarter.prob_cutoff), (proved.threshold, proved.prob_cutoff));
+	write_case_file("both", "3, 1000\nPercentile(1, 25)\nProve(Pr[X >= 1] >= 0.25)\n");
+	assert_eq!(get_case("both").unwrap().prob_cutoff, 0.25);
+    }
+
+    #[test]
+    fn percentile_alone_replaces_the_cutoff_on_the_first_line() {
+	use_temp_root("percentile_alone");
+	write_case_file("alone", "1, 0.1, 3, 1000\nPercentile(25)\n");
+	let alone = get_case("alone").unwrap();
+	assert_eq!((alone.threshold, alone.prob_cutoff), (1.0, 0.25));
+    }
+
+    #[test]
+    #[should_panic(expected = "so it must be s, p, k, d!")]
+    fn percentile_alone_needs_the_threshold_on_the_first_line() {
+	use_temp_root("percentile_no_threshold");
+	write_case_file("no_threshold", "3, 1000\nPercentile(25)\n");
+	get_case("no_threshold");
+    }
+
+    #[test]
+    fn half_cutoff_is_loaded_but_cannot_be_proved() {
+	use_temp_root("half_cutoff");
+	write_case_file("half", "0.5, 0.5, 3, 10\n");
+	write_case_file("below_half", "0.5, 0.45, 3, 10\n");
+	assert!(get_case("half").unwrap().cannot_be_proved());
+	assert!(!get_case("below_half").unwrap().cannot_be_proved());
+    }
 }
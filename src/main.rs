@@ -1,4 +1,4 @@
-use std::{env, io::{self, Write}, time::SystemTime};
+use std::{env, io::{self, Write}, panic, path::Path, time::{Duration, Instant, SystemTime}};
 
 mod prawitz;
 mod prover;
@@ -10,58 +10,878 @@ mod restriction;
 
 use prawitz::*;
 use util::*;
+use case::Case;
+
+// How far the endpoints of intervals may move before a run differs from its baseline.
+const BASELINE_TOLERANCE: f64 = 1e-9;
+
+/**
+ * The argument at the given index, or the empty string if there aren't enough
+ * arguments, so that commands can report bad arguments rather than panicking.
+ */
+fn arg<'a>(args: &[&'a str], index: usize) -> &'a str {
+    args.get(index).copied().unwrap_or("")
+}
+
+/**
+ * Reads the table named by the optional argument of generate and relax, from which
+ * to warm start precomputation #2. Returns Some(None) if no table was named, and
+ * None if the named table could not be read.
+ */
+fn read_warm_start(name: &str) -> Option<Option<Bounder>> {
+    match name {
+	"" => Some(None),
+	name => file_io::bounder_from_named_file(name).map(Some),
+    }
+}
+
+/**
+ * Removes an optional argument of the form name=value from the arguments,
+ * returning the value if there was one.
+ */
+fn take_option<'a>(args: &mut Vec<&'a str>, name: &str) -> Option<&'a str> {
+    let index = args.iter().position(|x| {
+	x.trim().strip_prefix(name).is_some_and(|rest| rest.starts_with('='))
+    })?;
+    Some(args.remove(index).trim()[name.len() + 1..].trim())
+}
+
+/**
+ * Removes the optional snapshot_every=N argument of generate and relax from their
+ * arguments. Returns Some(None) if there was no such argument, and None if N was
+ * not a positive integer.
+ */
+fn take_snapshot_every(args: &mut Vec<&str>) -> Option<Option<usize>> {
+    match take_option(args, "snapshot_every") {
+	Some(every) => {
+	    let every = every.parse().ok()?;
+	    (every > 0).then_some(Some(every))
+	}
+	None => Some(None),
+    }
+}
+
+/**
+ * Removes the optional gaussian=true|false argument of generate, relax and
+ * trace_cell from their arguments, which defaults to false. Returns None if the
+ * value was neither.
+ */
+fn take_gaussian(args: &mut Vec<&str>) -> Option<bool> {
+    match take_option(args, "gaussian") {
+	Some(value) => value.parse().ok(),
+	None => Some(false),
+    }
+}
+
+/**
+ * The time taken by each phase of a long computation, such as generate, to see
+ * which is worth optimising. Each phase starts when the previous one ends.
+ */
+struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+    phase_start: Instant,
+}
+
+impl Timings {
+    fn new() -> Timings {
+	Timings { phases: vec![], phase_start: Instant::now() }
+    }
+
+    fn end_phase(&mut self, name: &'static str) {
+	self.phases.push((name, self.phase_start.elapsed()));
+	self.phase_start = Instant::now();
+    }
+
+    fn print(&self) {
+	println!("PHASE TIMINGS:");
+	for (name, duration) in self.phases.iter() {
+	    println!("  {}: {:.1}s", name, duration.as_secs_f64());
+	}
+	println!("  total: {:.1}s", self.total().as_secs_f64());
+    }
+
+    fn total(&self) -> Duration {
+	self.phases.iter().map(|(_name, duration)| *duration).sum()
+    }
+}
+
+/**
+ * Runs precomputation #1 with fill, passing the #1 table to write_base, then
+ * warm starts from warm_from if there is one and runs precomputation #2 with
+ * relax, recording how long each of these phases takes in timings.
+ */
+fn precompute(fill: impl FnOnce() -> Bounder, write_base: impl FnOnce(&Bounder),
+	      warm_from: Option<&Bounder>, relax: impl FnOnce(&mut Bounder),
+	      timings: &mut Timings) -> Bounder {
+    let mut bounder = fill();
+    timings.end_phase("precomputation #1");
+    write_base(&bounder);
+    timings.end_phase("writing the #1 table");
+    if let Some(warm_from) = warm_from {
+	bounder.warm_start(warm_from);
+	timings.end_phase("warm start");
+    }
+    relax(&mut bounder);
+    timings.end_phase("precomputation #2");
+    bounder
+}
+
+/**
+ * Runs each of the named cases in turn, where run says whether every hypothesis
+ * of a case was proved, passing the progress so far to save after each one. Each
+ * line of the progress is the name of a case and its verdict: PASS, FAIL, or ERROR
+ * if it could not be run. The cases with a PASS or FAIL in the previous progress,
+ * from an earlier batch which was cut short, are skipped, and the rest re-run.
+ */
+fn run_batch(names: &[String], previous: &str, run: impl Fn(&str) -> bool,
+	     mut save: impl FnMut(&str)) {
+    let completed = previous.lines()
+	.filter_map(|line| match line.split_whitespace().collect::<Vec<&str>>()[..] {
+	    [name, verdict @ ("PASS" | "FAIL")] => Some((name.to_owned(), verdict)),
+	    _ => None,
+	})
+	.collect::<Vec<(String, &str)>>();
+    let mut progress = completed.iter()
+	.map(|(name, verdict)| format!("{} {}\n", name, verdict))
+	.collect::<String>();
+    if names.is_empty() {
+	println!("BATCH: there are no cases to run!");
+    }
+    for name in names.iter().map(|x| x.as_str()) {
+	if completed.iter().any(|(x, _verdict)| x == name) {
+	    println!("BATCH: skipping {}, which was already completed", name);
+	    continue;
+	}
+	println!("BATCH: running {}", name);
+	// A malformed case panics, which should not end the whole batch.
+	let verdict = match panic::catch_unwind(panic::AssertUnwindSafe(|| run(name))) {
+	    Ok(true) => "PASS",
+	    Ok(false) => "FAIL",
+	    Err(_) => "ERROR",
+	};
+	println!("BATCH: {} {}", name, verdict);
+	progress += &format!("{} {}\n", name, verdict);
+	save(&progress);
+    }
+}
+
+/**
+ * Compares the results of a run against the baseline saved in the given file,
+ * listing any differences after a line starting "Results DIFFER", so that scripts
+ * notice. If there is no baseline yet, these results are saved as the baseline
+ * instead.
+ */
+fn check_baseline(report: &prover::Report, case: &Case, filename: &str) {
+    let baseline = prover::Baseline::new(report, case);
+    match file_io::text_from_file(filename).map(|text| prover::Baseline::of_string(&text)) {
+	Some(Ok(saved)) => {
+	    let divergences = baseline.divergences(&saved, BASELINE_TOLERANCE);
+	    if divergences.is_empty() {
+		println!("Results match the baseline in {}.", filename);
+	    } else {
+		println!("Results DIFFER from the baseline in {}:", filename);
+		for divergence in divergences.iter() {
+		    println!("  {}", divergence);
+		}
+	    }
+	}
+	Some(Err(error)) => {
+	    println!("Failed to read the baseline in {}! {}", filename, error);
+	}
+	None => {
+	    file_io::text_to_file(filename, &format!("{}\n", baseline));
+	    println!("No baseline found, so saved these results as the baseline in {}.",
+		     filename);
+	}
+    }
+}
+
+/**
+ * Builds the pool in which the parallel parts of the computation are run, so
+ * every search and both precomputations are run inside pool.install. The pool is
+ * rebuilt by the threads command, so we cannot use rayon's global pool, which
+ * can only be set once. Zero threads means that rayon uses all available cores.
+ */
+fn build_pool(threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap()
+}
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
 
     let mut bounder = None;
+    let cli_args = env::args().collect::<Vec<String>>();
+    let threads = match cli_args.iter().position(|x| x == "--threads") {
+	Some(index) => cli_args.get(index + 1)
+	    .and_then(|x| x.parse().ok())
+	    .expect("Expected format: --threads N"),
+	None => 0,
+    };
+    // The number of decimal places in human-readable results; None prints in full.
+    let precision = cli_args.iter().position(|x| x == "--precision").map(|index| {
+	cli_args.get(index + 1)
+	    .and_then(|x| x.parse().ok())
+	    .expect("Expected format: --precision N")
+    });
+    // Whether run should estimate where the time in the search goes.
+    let profile = cli_args.iter().any(|x| x == "--profile");
+    // Whether to resolve nodes approximately; much faster, but not a proof.
+    let approx = cli_args.iter().any(|x| x == "--approx");
+    let mut pool = build_pool(threads);
     
-    fn prep(bounder: &mut Option<Bounder>) {
+    /**
+     * Loads the Bounder from file if needed. Returns None if there is no file to
+     * load, in which case the user needs to run generate first.
+     */
+    fn prep(bounder: &mut Option<Bounder>) -> Option<&Bounder> {
         if bounder.is_none() {
             println!("Running first time setup of Bounder object!");
             let start_time = SystemTime::now();
-            *bounder = Some(file_io::bounder_from_file());
+	    *bounder = file_io::bounder_from_file();
+	    if bounder.is_none() {
+		println!("No bounder found - run generate first!");
+		return None;
+	    }
             println!("Finsihed reading file. Duration (secs): {}",
 		     start_time.elapsed().unwrap().as_secs());
         }
+	bounder.as_ref()
     }
 
     loop {
         print!("Enter instruction: ");
         let _ = io::stdout().flush();
         let mut text = String::new();
-        io::stdin().read_line(&mut text).expect("Failed to read line");
+	// At the end of the input, e.g. when commands are piped in, we are done.
+	if io::stdin().read_line(&mut text).expect("Failed to read line") == 0 {
+	    println!();
+	    break;
+	}
         let (func, args) = parse_function_like(&text);
 	match func.trim().trim_end_matches(')').to_lowercase().as_str() {
 	    "run" => {
-		if let Some(case) = file_io::get_case(&args[0].to_owned()) {
-                    prep(&mut bounder);
-                    let start_time = SystemTime::now();
-                    prover::simulate(bounder.as_ref().unwrap(), case);
-                    println!("Simulation complete! Duration: {}s.",
-			     start_time.elapsed().unwrap().as_secs());
+		let mut args = args;
+		let baseline = take_option(&mut args, "baseline");
+		let json = take_option(&mut args, "json");
+		let stats = take_option(&mut args, "stats");
+		let tsv = take_option(&mut args, "tsv");
+		// The values of the first few a_i, below which alone to search.
+		let prefix = take_option(&mut args, "prefix");
+		let method = match take_option(&mut args, "method") {
+		    None | Some("full") => Some(BoundMethod::Full),
+		    Some("bernstein") => Some(BoundMethod::Bernstein),
+		    Some(_) => None,
+		};
+		let progress_dump = take_option(&mut args, "progress_dump")
+		    .map(|every| every.parse::<usize>().ok().filter(|every| *every > 0));
+		let name = arg(&args, 0);
+		if name.is_empty() {
+		    println!("Expected format: run(file)");
+		} else if method.is_none() {
+		    println!("Unknown method! Expected method=full or method=bernstein");
+		} else if progress_dump.is_some_and(|every| every.is_none()) {
+		    println!("Expected progress_dump=N, for a positive integer N");
+		} else if let Some(case) = file_io::get_case(name) {
+		    let case = case.with_method(method.unwrap());
+		    let values = match prefix {
+			Some(prefix) => split_list(prefix.trim_start_matches('[').trim_end_matches(']'))
+			    .iter().filter(|x| !x.is_empty()).map(|x| x.parse::<f64>())
+			    .collect::<Result<Vec<f64>, _>>(),
+			None => Ok(vec![]),
+		    };
+		    let prefix = values.as_ref().ok().map(|values| {
+			values.iter().map(|x| case.numerator_of(*x)).collect::<Vec<u128>>()
+		    });
+		    match prefix {
+			None => println!("Failed to parse the prefix! Expected format: run(file,prefix=[a_0,...,a_j])"),
+			Some(prefix) if prefix.len() > case.max_depth
+			    || prefix.windows(2).any(|pair| pair[1] > pair[0]) => {
+				println!("The prefix must be non-increasing, with at most {} terms!",
+					 case.max_depth);
+			    }
+			Some(prefix) => {
+			    if let Some(bounder) = prep(&mut bounder) {
+				let start_time = SystemTime::now();
+				let report = pool.install(|| {
+				    prover::simulate(bounder, &case, precision, &prefix, profile, approx,
+						     progress_dump.flatten())
+				});
+				if let Some(json) = json {
+				    let document = prover::RunDocument::new(&report, &case, bounder);
+				    file_io::text_to_file(json, &serde_json::to_string_pretty(&document).unwrap());
+				    println!("Wrote the results to {}.", json);
+				}
+				if let Some(stats) = stats {
+				    file_io::text_to_file(stats, &report.stats.to_csv());
+				    println!("Wrote the search tree statistics to {}.", stats);
+				}
+				if let Some(tsv) = tsv {
+				    file_io::text_to_file(tsv, &report.results.to_tsv(&case));
+				    println!("Wrote the results as a table to {}.", tsv);
+				}
+				if let Some(baseline) = baseline {
+				    check_baseline(&report, &case, baseline);
+				}
+				println!("Simulation complete! Duration: {}s.",
+					 start_time.elapsed().unwrap().as_secs());
+			    }
+			}
+		    }
+		} else if file_io::case_path(name).exists() {
+		    println!("Unknown case! No case {} in {}",
+			     name, file_io::case_path(name).display());
                 } else {
-                    println!("Unknown case!");
+		    println!("Unknown case! No file found at {}",
+			     file_io::case_path(name).display());
                 }
 	    }
+	    "batch" => {
+		let mut args = args;
+		let resume = take_option(&mut args, "resume") == Some("true");
+		let list = arg(&args, 0);
+		// Either a file listing the cases, or tag:t for every case tagged t.
+		let (names, progress_name) = match list.trim().strip_prefix("tag:") {
+		    Some(tag) => (file_io::cases_with_tag(tag.trim()),
+				  format!("tag_{}.progress", tag.trim())),
+		    None => (file_io::text_from_file(list).map(|names| {
+			names.lines().map(|x| x.trim().to_owned()).filter(|x| !x.is_empty())
+			    .collect::<Vec<String>>()
+		    }), format!("{}.progress", list)),
+		};
+		match names {
+		    None => println!("Failed to read a list of cases from {}! Expected format: batch(file), batch(tag:t), or either with resume=true",
+				     list),
+		    Some(names) => if let Some(bounder) = prep(&mut bounder) {
+			let previous = if resume {
+			    file_io::text_from_file(&progress_name).unwrap_or_default()
+			} else {
+			    String::new()
+			};
+			run_batch(&names, &previous, |name| {
+			    let case = file_io::get_case(name)
+				.unwrap_or_else(|| panic!("Unknown case {}!", name));
+			    let report = pool.install(|| {
+				prover::simulate(bounder, &case, precision, &[], profile, approx, None)
+			    });
+			    report.outcomes.iter().all(|outcome| outcome.proved)
+			}, |progress| file_io::text_to_file(&progress_name, progress));
+			println!("BATCH: finished; the verdicts are in {}", progress_name);
+		    }
+		}
+	    }
+	    "coverage" => {
+		match file_io::all_cases() {
+		    Some(cases) => if let Some(bounder) = prep(&mut bounder) {
+			let mut rows = vec![];
+			for (name, case) in cases {
+			    // Without any hypotheses, there is nothing to prove.
+			    if case.hypotheses.is_empty() {
+				println!("COVERAGE: skipping {}, which has no hypotheses", name);
+				continue;
+			    }
+			    println!("COVERAGE: running {}", name);
+			    let report = pool.install(|| {
+				prover::simulate(bounder, &case, precision, &[], profile, approx, None)
+			    });
+			    rows.push((name, report));
+			}
+			prover::print_coverage(&rows);
+		    }
+		    None => println!("No cases directory found!"),
+		}
+	    }
+	    "list" => {
+		match file_io::list_cases() {
+		    Some(cases) => {
+			for (name, headers) in cases.iter() {
+			    if headers.is_empty() {
+				println!("{}: (empty)", name);
+			    } else if headers.len() == 1 {
+				println!("{}: {}", name, headers[0]);
+			    } else {
+				for (index, header) in headers.iter().enumerate() {
+				    println!("{}:{}: {}", name, index, header);
+				}
+			    }
+			}
+		    }
+		    None => println!("No cases directory found!"),
+		}
+	    }
+	    "epsilonsensitivity" | "epsilon_sensitivity" => {
+		match file_io::get_case(arg(&args, 0)) {
+		    Some(case) => if let Some(bounder) = prep(&mut bounder) {
+			let base = pool.install(|| {
+			    prover::simulate(bounder, &case, precision, &[], profile, approx, None)
+			});
+			let mut runs = vec![];
+			for factor in [2.0, 0.5] {
+			    let epsilon = case.resolve_epsilon * factor;
+			    println!("EPSILON SENSITIVITY: running with epsilon {}", epsilon);
+			    let case = case.with_resolve_epsilon(epsilon);
+			    let report = pool.install(|| {
+				prover::simulate(bounder, &case, precision, &[], profile, approx, None)
+			    });
+			    runs.push((epsilon, report));
+			}
+			prover::print_epsilon_sensitivity(&case, &base, &runs);
+		    }
+		    None => println!("Failed to parse arguments! Expected format: epsilon_sensitivity(file)"),
+		}
+	    }
+	    "minimizethreshold" | "minimize_threshold" => {
+		match (file_io::get_case(arg(&args, 0)), arg(&args, 1).trim().parse::<f64>()) {
+		    (Some(case), _) if case.hypotheses.is_empty() => {
+			println!("The case has no hypotheses to prove!");
+		    }
+		    (Some(case), Ok(tolerance)) if tolerance > 0.0 => {
+			if let Some(bounder) = prep(&mut bounder) {
+			    let proves = |threshold: f64| {
+				println!("MINIMIZE THRESHOLD: running with threshold {}", threshold);
+				let case = case.with_threshold(threshold);
+				let report = pool.install(|| {
+				    prover::simulate(bounder, &case, precision, &[], profile, approx, None)
+				});
+				report.outcomes.iter().all(|outcome| outcome.proved)
+			    };
+			    let floor = bounder.threshold_range().0;
+			    match prover::minimize_threshold(case.threshold, floor, tolerance, proves) {
+				Some(threshold) => println!("Every hypothesis is proved with threshold {} (to within {}).",
+							    threshold, tolerance),
+				None => println!("Not every hypothesis is proved, even with threshold {}.", floor),
+			    }
+			}
+		    }
+		    _ => println!("Failed to parse arguments! Expected format: minimize_threshold(file,tolerance)"),
+		}
+	    }
+	    "sweep" => {
+		let denominators = args.iter().skip(1).map(|x| x.trim().parse::<u128>())
+		    .collect::<Result<Vec<u128>, _>>();
+		match (file_io::get_case(arg(&args, 0)), denominators) {
+		    (Some(case), Ok(denominators)) if !denominators.is_empty() => {
+			if denominators.iter().any(|d| *d == 0 || !case::square_sums_fit(*d, case.max_depth)) {
+			    println!("Each denominator must be positive, with k(d+1)^2 < 2^128!");
+			} else if let Some(bounder) = prep(&mut bounder) {
+			    let mut rows = vec![];
+			    for denominator in denominators {
+				println!("SWEEP: running with denominator {}", denominator);
+				let case = case.with_denominator(denominator);
+				case.warn_empty_numerator_ranges(arg(&args, 0));
+				let report = pool.install(|| {
+				    prover::simulate(bounder, &case, precision, &[], profile, approx, None)
+				});
+				rows.push((denominator, report));
+			    }
+			    prover::print_sweep("denominator", &rows);
+			}
+		    }
+		    _ => println!("Failed to parse arguments! Expected format: sweep(file,d_0,...,d_j)"),
+		}
+	    }
+	    "depthsweep" | "depth_sweep" => {
+		let depths = (arg(&args, 1).trim().parse::<usize>(), arg(&args, 2).trim().parse::<usize>());
+		match (file_io::get_case(arg(&args, 0)), depths) {
+		    (Some(case), (Ok(min_depth), Ok(max_depth))) => {
+			if min_depth == 0 || min_depth > max_depth {
+			    println!("Need 1 <= d_min <= d_max!");
+			} else if !case::square_sums_fit(case.denominator, max_depth) {
+			    println!("The denominator {} is too large for depth {}!", case.denominator, max_depth);
+			} else if let Some(bounder) = prep(&mut bounder) {
+			    let rows = prover::sweep_depths(&case, min_depth, max_depth, |case| {
+				case.warn_empty_numerator_ranges(arg(&args, 0));
+				pool.install(|| {
+				    prover::simulate(bounder, case, precision, &[], profile, approx, None)
+				})
+			    });
+			    prover::print_sweep("depth", &rows);
+			    match prover::first_proved(&rows) {
+				Some(depth) => println!("Every hypothesis is first proved at depth {}.", depth),
+				None => println!("No depth in this range proves every hypothesis."),
+			    }
+			}
+		    }
+		    _ => println!("Failed to parse arguments! Expected format: depth_sweep(file,d_min,d_max)"),
+		}
+	    }
+	    "slack" => {
+		let name = arg(&args, 0);
+		if name.is_empty() {
+		    println!("Expected format: slack(file)");
+		} else if let Some(case) = file_io::get_case(name) {
+		    if let Some(bounder) = prep(&mut bounder) {
+			prover::print_slack(bounder, &case);
+		    }
+		} else {
+		    println!("Unknown case! No file found at {}",
+			     file_io::case_path(name).display());
+		}
+	    }
 	    "d" => {
-		prep(&mut bounder);
-                if let (Ok(a), Ok(cutoff)) = (args[0].parse(), args[1].parse()) {
-                    bounder.as_ref().unwrap().print(a, cutoff)
-                } else {
-                    println!("Failed to parse arguments! Expected format: D(a,x)");
-                }
+		// Each argument is checked before the table is loaded, so that a typo
+		// is reported straight away, saying which argument was wrong.
+		let args = args.iter().map(|x| x.trim()).filter(|x| !x.is_empty())
+		    .collect::<Vec<&str>>();
+		match args[..] {
+		    [a, cutoff] => match (a.parse::<f64>(), cutoff.parse::<f64>()) {
+			(Err(e), _) => println!("Failed to parse the first argument a = {}: {}! Expected format: D(a,x)",
+						a, e),
+			(_, Err(e)) => println!("Failed to parse the second argument x = {}: {}! Expected format: D(a,x)",
+						cutoff, e),
+			(Ok(a), Ok(cutoff)) => if let Some(bounder) = prep(&mut bounder) {
+			    bounder.print(a, cutoff)
+			},
+		    },
+		    _ => println!("D takes two arguments, but got {}! Expected format: D(a,x)", args.len()),
+		}
+	    }
+	    "sandwich" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    if let (Ok(a), Ok(cutoff)) = (arg(&args, 0).parse(), arg(&args, 1).parse()) {
+			bounder.print_sandwich(a, cutoff)
+		    } else {
+			println!("Failed to parse arguments! Expected format: sandwich(a,x)");
+		    }
+		}
 	    }
 	    "generate" => {
-		println!("Running first time computation of Bounder object!");
-		let start_time = SystemTime::now();
-		let new_bounder = Bounder::new();
-		file_io::bounder_to_file(&new_bounder);
-		bounder = Some(new_bounder);
-		println!("Precomputation complete. Duration (secs): {}",
-			 start_time.elapsed().unwrap().as_secs());
-	    }
-	    &_ => println!("Unknown command! Valid commands: run, d, generate."),
+		let mut args = args;
+		let snapshot_every = take_snapshot_every(&mut args);
+		let gaussian = take_gaussian(&mut args);
+		// A final argument of f32 stores the table in single precision.
+		let single = args.last().map(|x| x.trim()) == Some("f32");
+		let args = &args[..args.len() - single as usize];
+		let q = match args.first().map(|x| x.trim()) {
+		    None | Some("") => Some(DEFAULT_Q),
+		    Some(text) => text.parse().ok().and_then(q_as_fraction),
+		};
+		let warm_name = arg(args, 1);
+		let warm_from = read_warm_start(warm_name);
+		if warm_from.is_none() {
+		    println!("No table of bounds found at {}!", warm_name);
+		} else if let (Some(q), Some(snapshot_every), Some(gaussian)) =
+		    (q, snapshot_every, gaussian) {
+		    println!("Running first time computation of Bounder object!");
+		    let start_time = SystemTime::now();
+		    let mut timings = Timings::new();
+		    let mut new_bounder = precompute(
+			|| pool.install(|| Bounder::fill_base(q)), file_io::base_bounder_to_file,
+			warm_from.flatten().as_ref(),
+			|bounder| pool.install(|| bounder.relax(D_ITERATIONS, snapshot_every, gaussian,
+								file_io::bounder_snapshot_to_file)),
+			&mut timings);
+		    if single {
+			let max_loss = new_bounder.store_as_single();
+			println!("Stored table as f32, decreasing entries by at most {:e}",
+				 max_loss);
+			timings.end_phase("conversion to f32");
+		    }
+		    file_io::bounder_to_file(&new_bounder);
+		    timings.end_phase("writing the table");
+		    bounder = Some(new_bounder);
+		    timings.print();
+		    println!("Precomputation complete. Duration (secs): {}",
+			     start_time.elapsed().unwrap().as_secs());
+		} else {
+		    println!("Failed to parse arguments! Expected format: generate, generate(q) or generate(q,file), with 0 < q <= 0.5, optionally followed by f32, snapshot_every=N and gaussian=true");
+		}
+	    }
+	    "relax" => {
+		let mut args = args;
+		let snapshot_every = take_snapshot_every(&mut args);
+		let gaussian = take_gaussian(&mut args);
+		let warm_name = arg(&args, 1);
+		let warm_from = read_warm_start(warm_name);
+		if warm_from.is_none() {
+		    println!("No table of bounds found at {}!", warm_name);
+		} else if let (Ok(iters), Some(snapshot_every), Some(gaussian)) =
+		    (arg(&args, 0).trim().parse(), snapshot_every, gaussian) {
+		    println!("Re-running precomputation #2 from the stored #1 table!");
+		    let start_time = SystemTime::now();
+		    if let Some(mut new_bounder) = file_io::base_bounder_from_file() {
+			if let Some(Some(warm_from)) = warm_from {
+			    new_bounder.warm_start(&warm_from);
+			}
+			pool.install(|| new_bounder.relax(iters, snapshot_every, gaussian,
+							  file_io::bounder_snapshot_to_file));
+			file_io::bounder_to_file(&new_bounder);
+			bounder = Some(new_bounder);
+			println!("Precomputation complete. Duration (secs): {}",
+				 start_time.elapsed().unwrap().as_secs());
+		    } else {
+			println!("No base bounder found - run generate first!");
+		    }
+		} else {
+		    println!("Failed to parse arguments! Expected format: relax(iters) or relax(iters,file), optionally followed by snapshot_every=N and gaussian=true");
+		}
+	    }
+	    "below" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    let pars = args.iter().map(|x| x.trim().parse::<f64>()).collect::<Vec<_>>();
+		    if let [Ok(a), Ok(p)] = pars[..] {
+			bounder.print_below(a, p);
+		    } else {
+			println!("Failed to parse arguments! Expected format: below(a,p)");
+		    }
+		}
+	    }
+	    "defect" => {
+		let name = arg(&args, 0).trim();
+		if name.is_empty() {
+		    println!("Expected format: defect(file)");
+		} else if let Some(bounder) = prep(&mut bounder) {
+		    match file_io::bounder_from_named_file(name) {
+			Some(reference) => bounder.print_defect(&reference),
+			None => println!("No table found at {}!", name),
+		    }
+		}
+	    }
+	    "dvec" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    let pars = args.iter().map(|x| x.trim().parse::<f64>()).collect::<Vec<_>>();
+		    if let [Ok(a), Ok(x0), Ok(x1), Ok(step)] = pars[..] {
+			if step > 0.0 {
+			    bounder.print_range(a, x0, x1, step);
+			} else {
+			    println!("The step must be positive!");
+			}
+		    } else {
+			println!("Failed to parse arguments! Expected format: dvec(a,x0,x1,step)");
+		    }
+		}
+	    }
+	    "explain" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    let pars = args.iter().map(|x| x.trim().parse::<f64>()).collect::<Vec<_>>();
+		    match pars[..] {
+			[Ok(a), Ok(cutoff)] => bounder.print_explanation(a, cutoff, None),
+			[Ok(a), Ok(cutoff), Ok(min_var), Ok(max_var)] => {
+			    bounder.print_explanation(a, cutoff, Some((min_var, max_var)))
+			}
+			_ => println!("Failed to parse arguments! Expected format: explain(a,x) or explain(a,x,min_var,max_var)"),
+		    }
+		}
+	    }
+	    "tracecell" | "trace_cell" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    let mut args = args;
+		    let gaussian = take_gaussian(&mut args);
+		    let pars = args.iter().map(|x| x.trim().parse::<usize>()).collect::<Vec<_>>();
+		    match (&pars[..], gaussian) {
+			(&[Ok(a), Ok(y)], Some(gaussian)) if a < bounder.bounds().num_rows()
+			    && y < bounder.bounds().row_len(a) => bounder.trace_cell(a, y, gaussian),
+			(&[Ok(_), Ok(_)], Some(_)) => println!("Indices out of range! The table has {} rows of {} entries.",
+						   bounder.bounds().num_rows(), bounder.bounds().row_len(0)),
+			_ => println!("Failed to parse arguments! Expected format: trace_cell(a_index,y_index), optionally followed by gaussian=true"),
+		    }
+		}
+	    }
+	    "thresholdof" | "threshold_of" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    match arg(&args, 0).trim().parse() {
+			Ok(y) => bounder.print_threshold_of(y),
+			Err(_) => println!("Failed to parse arguments! Expected format: threshold_of(y_index)"),
+		    }
+		}
+	    }
+	    "yof" | "y_of" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    match arg(&args, 0).trim().parse() {
+			Ok(cutoff) => bounder.print_threshold_of(bounder.y_of(cutoff)),
+			Err(_) => println!("Failed to parse arguments! Expected format: y_of(x)"),
+		    }
+		}
+	    }
+	    "roundsensitivity" | "round_sensitivity" => {
+		let pars = args.iter().map(|x| x.trim().parse::<usize>()).collect::<Vec<_>>();
+		match pars[..] {
+		    [Ok(a), Ok(y)] => print_round_sensitivity(a, y, DEFAULT_Q),
+		    _ => println!("Failed to parse arguments! Expected format: round_sensitivity(a_index,y_index)"),
+		}
+	    }
+	    "checkmono" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    bounder.print_monotonicity();
+		}
+	    }
+	    "prawitz" => {
+		// With a fourth argument "exact", small a are not increased to 0.1.
+		let exact = arg(&args, 3).trim() == "exact";
+		let pars = args.iter().take(3).map(|x| x.trim().parse::<f64>()).collect::<Vec<_>>();
+		match pars[..] {
+		    [Ok(a), Ok(x), Ok(g)] if args.len() == 3 || exact => {
+			if a > 0.0 && a <= 1.0 && g >= 0.0 {
+			    let bound = if exact {
+				prawitz_bound_exact(a, x, 0.5, g)
+			    } else {
+				prawitz_bound_gaussian(a, x, 0.5, g)
+			    };
+			    println!("Pr[X + G > {}] >= {}", x, bound);
+			} else {
+			    println!("Need 0 < a <= 1 and g >= 0!");
+			}
+		    }
+		    _ => println!("Failed to parse arguments! Expected format: prawitz(a,x,g) or prawitz(a,x,g,exact)"),
+		}
+	    }
+	    "eval" => {
+		let mut args = args;
+		let denominator = take_option(&mut args, "denominator")
+		    .map(|d| d.parse::<u128>().ok().filter(|d| *d > 0));
+		let pars = args.iter().map(|x| x.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>();
+		match pars.as_deref() {
+		    Ok([threshold, coefs @ ..]) => {
+			let mut coefs = coefs.to_vec();
+			coefs.sort_by(|a, b| b.total_cmp(a));
+			let square_sum = coefs.iter().map(|a| a * a).sum::<f64>();
+			if coefs.iter().any(|a| *a < 0.0) || square_sum > 1.0 + 1e-12 {
+			    println!("Need non-negative a_i with sum of squares at most 1!");
+			} else if coefs.len() > 30 {
+			    println!("Too many coefficients to try every choice of signs!");
+			} else if denominator.is_some_and(|d| d.is_none()) {
+			    println!("Expected denominator=d, for a positive integer d");
+			} else if let Some(bounder) = prep(&mut bounder) {
+			    println!("Pr[X >= {}] >= {}", threshold,
+				     prover::point_lower_bound(bounder, *threshold, &coefs));
+			    if let Some(denominator) = denominator.flatten() {
+				// The bound the search would give for the box of intervals
+				// containing the coefficients.
+				let seq = prover::Seq::from_coefficients(&coefs, denominator);
+				println!("Pr[X >= {}] >= {} for a_i in the intervals over {} containing them",
+					 threshold, seq.box_lower_bound(bounder, *threshold,
+									BoundMethod::Full, true,
+									coefs.len()),
+					 denominator);
+			    }
+			}
+		    }
+		    _ => println!("Failed to parse arguments! Expected format: eval(threshold,a_0,...,a_k), optionally followed by denominator=d"),
+		}
+	    }
+	    "condeval" | "cond_eval" => {
+		let signs_code = arg(&args, 1).trim().parse::<u64>();
+		let pars = args.iter().enumerate().filter(|(i, _x)| *i != 1)
+		    .map(|(_i, x)| x.trim().parse::<f64>())
+		    .collect::<Result<Vec<_>, _>>();
+		match (signs_code, pars.as_deref()) {
+		    (Ok(signs_code), Ok([threshold, coefs @ ..])) => {
+			let square_sum = coefs.iter().map(|a| a * a).sum::<f64>();
+			if coefs.iter().any(|a| *a < 0.0) || square_sum > 1.0 + 1e-12 {
+			    println!("Need non-negative a_i with sum of squares at most 1!");
+			} else if coefs.windows(2).any(|pair| pair[0] < pair[1]) {
+			    println!("Need a_0 >= a_1 >= ... >= a_k, so that bit i is the sign of a_i!");
+			} else if coefs.len() > 63 || signs_code >> coefs.len() != 0 {
+			    println!("Need a sign pattern of at most {} bits!", coefs.len());
+			} else if let Some(bounder) = prep(&mut bounder) {
+			    let signs = (0..coefs.len())
+				.map(|i| if (signs_code >> i) % 2 == 1 { '-' } else { '+' })
+				.collect::<String>();
+			    println!("Pr[X >= {} | signs {}] >= {}", threshold, signs,
+				     prover::conditional_lower_bound(bounder, *threshold, coefs,
+								     signs_code));
+			}
+		    }
+		    _ => println!("Failed to parse arguments! Expected format: condeval(threshold,signs,a_0,...,a_k)"),
+		}
+	    }
+	    "terms" => {
+		match arg(&args, 0).trim().parse::<f64>() {
+		    Ok(a) if a > 0.0 && a <= 1.0 => {
+			println!("n = 1/a^2 = {}, i.e. about {} equal terms",
+				 equal_terms(a), equal_terms(a).round());
+		    }
+		    Ok(_) => println!("Need 0 < a <= 1!"),
+		    Err(_) => println!("Failed to parse arguments! Expected format: terms(a)"),
+		}
+	    }
+	    "gauss" => {
+		if let Ok(x) = arg(&args, 0).trim().parse() {
+		    println!("Pr[Z > {}] = {}", x, gaussian_tail(x));
+		} else {
+		    println!("Failed to parse arguments! Expected format: gauss(x)");
+		}
+	    }
+	    "threads" => {
+		if let Ok(threads) = arg(&args, 0).trim().parse() {
+		    pool = build_pool(threads);
+		    println!("Using {} threads.", pool.current_num_threads());
+		} else {
+		    println!("Failed to parse arguments! Expected format: threads(N)");
+		}
+	    }
+	    "heatmap" => {
+		if let Some(bounder) = prep(&mut bounder) {
+		    if arg(&args, 0).is_empty() {
+			println!("Expected format: heatmap(out.png)");
+		    } else if let Err(e) = file_io::bounder_to_heatmap(bounder, Path::new(arg(&args, 0))) {
+			println!("Failed to write heatmap: {}", e);
+		    }
+		}
+	    }
+	    "info" => {
+		println!("Threads: {}", pool.current_num_threads());
+		match bounder.as_ref() {
+		    Some(bounder) => println!("Bounder loaded, with header {} and fingerprint {:016x}",
+					      bounder.header_line(), bounder.fingerprint()),
+		    None => println!("No Bounder loaded."),
+		}
+	    }
+	    &_ => println!("Unknown command! Valid commands: run, sweep, depth_sweep, epsilon_sensitivity, minimize_threshold, batch, coverage, list, d, dvec, explain, below, sandwich, slack, generate, relax, checkmono, defect, trace_cell, threshold_of, y_of, round_sensitivity, prawitz, eval, condeval, terms, gauss, heatmap, threads, info."),
 	}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timings_sum_to_the_total() {
+	let start = Instant::now();
+	let mut timings = Timings::new();
+	std::thread::sleep(Duration::from_millis(20));
+	timings.end_phase("first");
+	std::thread::sleep(Duration::from_millis(10));
+	timings.end_phase("second");
+	let elapsed = start.elapsed();
+	let phases = timings.phases.iter().map(|(_name, duration)| *duration).collect::<Vec<_>>();
+	assert!(phases[0] >= Duration::from_millis(20) && phases[1] >= Duration::from_millis(10));
+	assert_eq!(timings.total(), phases[0] + phases[1]);
+	assert!(timings.total() <= elapsed);
+    }
+
+    fn phase_names(warm_from: Option<&Bounder>) -> Vec<&'static str> {
+	let mut timings = Timings::new();
+	precompute(|| Bounder::fill_base_sized(DEFAULT_Q, 10, 10), |_bounder| (), warm_from,
+		   |bounder| bounder.relax(2, None, false, |_bounder, _iteration| ()),
+		   &mut timings);
+	timings.phases.iter().map(|(name, _duration)| *name).collect()
+    }
+
+    #[test]
+    fn precomputation_records_each_phase() {
+	assert_eq!(phase_names(None),
+		   ["precomputation #1", "writing the #1 table", "precomputation #2"]);
+	let warm_from = Bounder::fill_base_sized(DEFAULT_Q, 10, 10);
+	assert_eq!(phase_names(Some(&warm_from)),
+		   ["precomputation #1", "writing the #1 table", "warm start", "precomputation #2"]);
+    }
+
+    #[test]
+    fn resumed_batch_runs_only_the_remainder() {
+	let names = ["a", "b", "c", "d"].map(String::from);
+	// The first batch was cut short after b, and c could not be run.
+	let previous = "a PASS\nb FAIL\nc ERROR\n";
+	let ran = std::cell::RefCell::new(vec![]);
+	let mut saved = String::new();
+	run_batch(&names, previous, |name| {
+	    ran.borrow_mut().push(name.to_owned());
+	    if name == "d" {
+		panic!("Unknown case d!");
+	    }
+	    true
+	}, |progress| saved = progress.to_owned());
+	assert_eq!(ran.into_inner(), ["c", "d"]);
+	assert_eq!(saved, "a PASS\nb FAIL\nc PASS\nd ERROR\n");
+    }
+}
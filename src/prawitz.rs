@@ -1,6 +1,9 @@
-use std::io::{self, Write};
+use std::{io::{self, Write}, sync::{OnceLock, atomic::{AtomicBool, Ordering}}};
 
 use cached::proc_macro::cached;
+use rayon::prelude::*;
+
+use crate::util::gaussian_tail;
 
 /**
  * This code is a direct translation of the code from the paper of Dvorak and Klein.
@@ -12,8 +15,15 @@ use cached::proc_macro::cached;
 
 const DEFAULT_EPSILON: f64 = 0.001;
 const PI: f64 = std::f64::consts::PI;
-const D_ITERATIONS: usize = 1000;
+pub const D_ITERATIONS: usize = 1000;
 const N: usize = 2000;
+// The default split point between the two integrals in compute_f, as a fraction.
+pub const DEFAULT_Q: (usize, usize) = (1, 2);
+// The table generated here covers thresholds from -THRESHOLD_RANGE to THRESHOLD_RANGE.
+pub const THRESHOLD_RANGE: usize = 3;
+// Within this distance of the top of the table, most values are beyond it.
+const NEAR_TOP_OF_TABLE: f64 = 0.5;
+const Q_GRANULARITY: usize = 1000;
 
 // The solution of exp(-x^2/2)+cos(x) = 0 with x in [0, pi]
 const THETA: f64 = 1.7780882886686339603;
@@ -23,6 +33,14 @@ fn normal_char(x: f64) -> f64 {
     (- x * x / 2.0).exp()
 }
 
+// The bounds below are specific to Rademacher signs. They rely on X being
+// symmetric, so that f_X is real and k can be taken real, and on each factor
+// of f_X being cos(a_i v). Signs with a bias P(e = +1) = p != 1/2 would give a
+// complex f_X, whose factors have modulus sqrt(1 - 4p(1-p) sin^2(a_i v)), and
+// need the complex form of the Prawitz kernel, with new Lipschitz constants in
+// compute_f. Until those are worked out, there is no bias parameter here, as
+// a bound which was not proved would be worse than none.
+
 // An upper bound on |f_X(v)|, given an upper bound on a1
 // This is h(v, a) from page 12 of the paper. Note there are more cases there
 // not used here.
@@ -56,8 +74,13 @@ fn k(u: f64, x: f64, t: f64) -> f64 {
     }
 }
 
-fn lipschitz_integrate(f: &dyn Fn(f64) -> f64, start: f64, end: f64, epsilon: f64, derivative_bound: f64, max_f_error: f64) -> f64 {
+// Returns None if the error in computing f alone, over the whole interval, is
+// already at least epsilon, as then no number of steps would do.
+fn lipschitz_integrate(f: &dyn Fn(f64) -> f64, start: f64, end: f64, epsilon: f64, derivative_bound: f64, max_f_error: f64) -> Option<f64> {
     let width = end - start;
+    if max_f_error * width >= epsilon {
+	return None;
+    }
     let num_steps = (2.0 + derivative_bound * width.powi(2) / (4.0 * (epsilon - max_f_error * width))) as usize;
     // ensures the implied error is smaller than epsilon
     let error = derivative_bound * width.powi(2) / (4.0 * num_steps as f64) + width * max_f_error;
@@ -66,53 +89,96 @@ fn lipschitz_integrate(f: &dyn Fn(f64) -> f64, start: f64, end: f64, epsilon: f6
     for k in 0..num_steps {
         sum += f(start + (2 * k + 1) as f64 * width / (2.0 * num_steps as f64));
     }
-    (end - start) * sum / num_steps as f64
+    Some((end - start) * sum / num_steps as f64)
 }
 
-fn compute_f(a1: f64, x: f64, t: f64, q: f64, epsilon: f64) -> f64 {
+// Here g is the variance of an independent Gaussian added to X, which multiplies
+// every characteristic function by normal_char(sqrt(g) * v). Normally g = 0.
+fn compute_f(a1: f64, x: f64, t: f64, q: f64, epsilon: f64, g: f64) -> f64 {
     let tx = (t * x).abs();
     // The three integrands are Lipschitz with the following constants.
     // The Bounds are derived in Appendix titled "Numeric integration in our proofs"
     // of:   https://arxiv.org/pdf/2006.16834.pdf
-    let bound1 = t * (1.0 + 2.0 * tx / PI) + 1.1 * (tx.powi(2) / (2.0 * PI) + PI);
-    let bound2 = t * (1.0 + 2.0 * tx / PI) + tx.powi(2) / (2.0 * PI) + PI;
-    let bound3 = 2.0 * (t / 3.0) *(1.0 + 2.0 * tx / PI) + tx.powi(2) / (2.0 * PI) + PI;
+    // The Gaussian factor h(u) = exp(-g (ut)^2 / 2) lies in [0, 1] and has
+    // |h'(u)| <= t sqrt(g). Each integrand without it is at most |k(u, x, T)|,
+    // which is at most 1 + tx/2 + 1/pi (using sin(pi u) >= 2 min(u, 1 - u)), so
+    // the product rule adds at most (1.5 + tx/2) t sqrt(g) to each constant.
+    let gaussian_extra = (1.5 + tx / 2.0) * t * g.sqrt();
+    let bound1 = t * (1.0 + 2.0 * tx / PI) + 1.1 * (tx.powi(2) / (2.0 * PI) + PI)
+	+ gaussian_extra;
+    let bound2 = t * (1.0 + 2.0 * tx / PI) + tx.powi(2) / (2.0 * PI) + PI + gaussian_extra;
+    let bound3 = 2.0 * (t / 3.0) *(1.0 + 2.0 * tx / PI) + tx.powi(2) / (2.0 * PI) + PI
+	+ gaussian_extra;
+    let h = |v: f64| normal_char(g.sqrt() * v);
     // Computing the integrated functions has absolute error < abs_error
     let abs_error = 2.0_f64.powi(-40) * (2.0 + tx);
 
     // the maximal additive errors sum to < eps
-    let sum1 = lipschitz_integrate(&|u| k(u, x, t).abs() * difference_bound(u*t, a1),
+    let sum1 = lipschitz_integrate(&|u| k(u, x, t).abs() * difference_bound(u*t, a1) * h(u*t),
         0.0, q, epsilon / 4.0, bound1, abs_error);
-    let sum2 = lipschitz_integrate(&|u| k(u, x, t).abs() * fx_bound(u*t, a1),
+    let sum2 = lipschitz_integrate(&|u| k(u, x, t).abs() * fx_bound(u*t, a1) * h(u*t),
         q, 1.0, epsilon / 4.0, bound2, abs_error);
-    let sum3 = lipschitz_integrate(&|u| k(u, x, t) * normal_char(u*t),
+    let sum3 = lipschitz_integrate(&|u| k(u, x, t) * normal_char(u*t) * h(u*t),
         0.0, q, epsilon / 4.0, bound3, abs_error);
 
-    // the value of F, minus the additive error allowed in the integration.
-    // only one epsilon needed here as at most epsilon/4 error in each summand.
-    0.5 - epsilon - (sum1 + sum2 + sum3)
+    match (sum1, sum2, sum3) {
+	// the value of F, minus the additive error allowed in the integration.
+	// only one epsilon needed here as at most epsilon/4 error in each summand.
+	(Some(sum1), Some(sum2), Some(sum3)) => 0.5 - epsilon - (sum1 + sum2 + sum3),
+	// For very large tx we cannot evaluate the integrands accurately enough,
+	// so we fall back to the trivial lower bound.
+	_ => 0.0,
+    }
 }
 
 // lower bound on Pr[X > x] for a Rademacher sum X
 // with largest coefficient <= a, and Variance = 1.
-// Just an application of F with T = pi/a, q = 0.5.
+// Just an application of F with T = pi/a, and q = 0.5 by default.
 // We pass things in as fractions so that we play nicely with #cached.
+// The corner a = 1 (where X = +-1) needs no special handling: with T = pi/a we
+// evaluate fx_bound and difference_bound only at a1 * v = u * pi, with u < 1 and
+// u <= q <= 1/2 respectively, whatever a is. The same goes for the slightly
+// larger a which round_up produces from the last row of the table.
 #[cached]
-pub fn prawitz_bound(a_num: i32, a_denom: usize, x_num: i32, x_denom: usize) -> f64 {
+pub fn prawitz_bound(a_num: i32, a_denom: usize, x_num: i32, x_denom: usize,
+		     q_num: usize, q_denom: usize) -> f64 {
     let a = a_num as f64 / a_denom as f64;
     let x = x_num as f64 / x_denom as f64;
+    let q = q_num as f64 / q_denom as f64;
     // If a1 is small, we increase it for efficiency reasons.
     // This is allowed --
     //   F2 lower bounds the supremum of Pr[X > x] where X is a
     //   normalized Rademacher sums with largest coefficient <= a1.
     if a < 0.1 {
-        prawitz_bound(1, 10, x_num, x_denom)
+	prawitz_bound(1, 10, x_num, x_denom, q_num, q_denom)
     } else {
-        let out = compute_f(a, x, PI/a, 0.5, DEFAULT_EPSILON).max(0.0);
+	let out = compute_f(a, x, PI/a, q, DEFAULT_EPSILON, 0.0).max(0.0);
         out
     }
 }
 
+/**
+ * A lower bound on Pr[X + G > x], where X is as in prawitz_bound and G is an
+ * independent Gaussian of mean 0 and variance g. With g = 0 this agrees with
+ * prawitz_bound, except that a and x are not rounded to fractions.
+ * The table is only ever built with g = 0, as precomputation #2, the rescaling in
+ * get_with_var and Bernstein's inequality all assume that there is no Gaussian
+ * part, so this is only available as a one-off computation.
+ */
+pub fn prawitz_bound_gaussian(a: f64, x: f64, q: f64, g: f64) -> f64 {
+    prawitz_bound_exact(a.max(0.1), x, q, g)
+}
+
+/**
+ * As prawitz_bound_gaussian, but without increasing a to 0.1 when it is small, so
+ * that we can see how the bound converges to the Gaussian tail as a -> 0.
+ * The Lipschitz constants in compute_f grow like T^2 = (pi/a)^2, and so does the
+ * number of integration steps: a = 0.01 is around 100 times slower than a = 0.1.
+ */
+pub fn prawitz_bound_exact(a: f64, x: f64, q: f64, g: f64) -> f64 {
+    compute_f(a, x, PI/a, q, DEFAULT_EPSILON, g).max(0.0)
+}
+
 // round v to the next multiple of denom.
 fn round_up(v: i32, denom: usize) -> i32 {
     let d = denom as i32;
@@ -123,18 +189,272 @@ fn round_up(v: i32, denom: usize) -> i32 {
     }
 }
 
-pub fn prawitz_bound_raw(a: usize, y: usize, coef_granularity: usize, thresh_granularity: usize, max_bound: usize) -> f64 {
+pub fn prawitz_bound_raw(a: usize, y: usize, coef_granularity: usize, thresh_granularity: usize, max_bound: usize, q: (usize, usize)) -> f64 {
     prawitz_bound(round_up(a as i32, 16) + 1, coef_granularity,
-        round_up(y as i32 - max_bound as i32, 8) + 1, thresh_granularity)
+	round_up(y as i32 - max_bound as i32, 8) + 1, thresh_granularity, q.0, q.1)
+}
+
+/**
+ * The value which precomputation #1 gives to bounds[a][y], and the value it would
+ * give without round_up, which snaps a and the threshold to a coarser grid so that
+ * more of the calls to prawitz_bound are cached. Rounding up only weakens the
+ * bound, so the difference is the tightness this costs.
+ */
+pub fn round_sensitivity(a: usize, y: usize, q: (usize, usize)) -> (f64, f64) {
+    let max_bound = THRESHOLD_RANGE * N;
+    let snapped = prawitz_bound_raw(a, y, N, N, max_bound, q);
+    let exact = prawitz_bound(a as i32 + 1, N, y as i32 - max_bound as i32 + 1, N, q.0, q.1);
+    (snapped, exact)
+}
+
+/**
+ * Prints the two values given by round_sensitivity, and the difference between them.
+ */
+pub fn print_round_sensitivity(a: usize, y: usize, q: (usize, usize)) {
+    let max_bound = THRESHOLD_RANGE * N;
+    if a >= N || y >= 2 * max_bound {
+	println!("Indices out of range! The table has {} rows of {} entries.", N, 2 * max_bound);
+	return;
+    }
+    let (snapped, exact) = round_sensitivity(a, y, q);
+    let exact_a = a as i32 + 1;
+    let exact_x = y as i32 - max_bound as i32 + 1;
+    println!("a_1 <= {}, threshold {}", exact_a as f64 / N as f64, exact_x as f64 / N as f64);
+    println!("  with rounding (a_1 <= {}, threshold {}): {}",
+	     (round_up(a as i32, 16) + 1) as f64 / N as f64,
+	     (round_up(exact_x - 1, 8) + 1) as f64 / N as f64, snapped);
+    println!("  without rounding: {}", exact);
+    println!("  difference: {}", exact - snapped);
+}
+
+/**
+ * Converts a user-supplied split point q into a fraction for prawitz_bound.
+ * Since T = pi/a, difference_bound is only valid for q <= 1/2.
+ */
+pub fn q_as_fraction(q: f64) -> Option<(usize, usize)> {
+    if q > 0.0 && q <= 0.5 {
+	Some(((q * Q_GRANULARITY as f64).round() as usize, Q_GRANULARITY))
+    } else {
+	None
+    }
 }
 
 ////// DYNAMIC PROGRAMMING //////
 
+// A list of entries (a, y, magnitude) of the table which break monotonicity.
+type Violations = Vec<(usize, usize, f64)>;
+
+// Below this cutoff we also consider Bernstein's inequality.
+const BERNSTEIN_CUTOFF: f64 = -3.0;
+
+/**
+ * Bernstein's inequality; from
+ * https://en.wikipedia.org/wiki/Bernstein_inequalities_(probability_theory)
+ * (first one in 'some of the inequalities' section.)
+ */
+fn get_bernstein(a: f64, t: f64) -> f64 {
+    1.0 - ((- (t * t)) / (2.0 * (1.0 - (a * t / 3.0)))).exp()
+}
+
+/**
+ * The Chernoff bound from the exact moment generating function: for s > 0,
+ *     Pr[X <= t] <= exp(s t) E[exp(-s X)] = exp(s t + sum log cosh(s a_i)).
+ * As log cosh(s sqrt(u)) is concave in u, subject to sum a_i^2 = 1 and a_i <= a the
+ * sum is largest when the a_i are split into arbitrarily many tiny pieces, where
+ * it tends to s^2 / 2. So the worst case does not depend on a, and minimising
+ * s t + s^2 / 2 over s gives s = -t, and Pr[X > t] >= 1 - exp(-t^2 / 2) for t < 0.
+ * So this takes no a, and needs no numerical search over s, as the optimum is
+ * known exactly. This is always at least as good as Bernstein's inequality.
+ */
+fn get_chernoff(t: f64) -> f64 {
+    if t < 0.0 {
+	1.0 - (-(t * t) / 2.0).exp()
+    } else {
+	0.0
+    }
+}
+
+/**
+ * Hoeffding's inequality, Pr[X >= t] <= exp(-t^2 / 2), which holds for every
+ * t >= 0 as sum a_i^2 = 1. For t < 0 we only have the trivial bound of 1.
+ */
+fn get_hoeffding(t: f64) -> f64 {
+    if t >= 0.0 {
+	(-(t * t) / 2.0).exp()
+    } else {
+	1.0
+    }
+}
+
+/**
+ * Which bounds get_with_method may use: everything available, or only Bernstein's
+ * inequality, to measure how much the table adds over it.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundMethod {
+    Full,
+    Bernstein,
+}
+
+// Below this largest coefficient we also consider the Gaussian floor.
+const GAUSSIAN_FLOOR_MAX_A: f64 = 0.05;
+// Shevtsova's constant in the Berry-Esseen theorem for non-identical summands.
+const BERRY_ESSEEN: f64 = 0.56;
+// The fractional error of erfc, and so of gaussian_tail.
+const ERFC_ERROR: f64 = 1.2e-7;
+
+/**
+ * The Gaussian floor: by the Berry-Esseen theorem, P[X <= t] differs from that for
+ * a standard normal by at most BERRY_ESSEEN * sum |a_i|^3 <= BERRY_ESSEEN * a, as
+ * sum a_i^2 = 1. So P[X > t] >= P[Z > t] - BERRY_ESSEEN * a, which we weaken a
+ * little further to allow for the error in computing P[Z > t].
+ */
+fn get_gaussian(a: f64, t: f64) -> f64 {
+    (gaussian_tail(t) * (1.0 - ERFC_ERROR) - BERRY_ESSEEN * a).max(0.0)
+}
+
+/**
+ * How a table compares with a reference table, as found by Bounder::defect.
+ */
+struct Defect {
+    improved: usize,
+    regressed: usize,
+    unchanged: usize,
+    gains: f64,
+    losses: f64,
+    // The entry (a, y) which decreased the most, and by how much (as a negative).
+    worst: Option<(usize, usize, f64)>,
+}
+
+/**
+ * Where the value returned by Bounder::get came from.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoundSource {
+    Table,
+    Bernstein,
+    Chernoff,
+    Gaussian,
+    BeyondTable,
+}
+
+/**
+ * A value of Bounder::get, along with how it was derived.
+ */
+pub struct Explanation {
+    pub value: f64,
+    pub source: BoundSource,
+    pub a_index: usize,
+    pub cutoff_index: usize,
+    pub table_value: f64,
+    pub bernstein_value: Option<f64>,
+    pub chernoff_value: f64,
+    pub gaussian_value: Option<f64>,
+}
+
+/**
+ * Read access to a table of bounds, however its entries are stored.
+ */
+pub trait Table {
+    fn num_rows(&self) -> usize;
+    fn row_len(&self, a: usize) -> usize;
+    fn entry(&self, a: usize, y: usize) -> f64;
+}
+
+impl<T: Copy + Into<f64>> Table for Vec<Vec<T>> {
+    fn num_rows(&self) -> usize {
+	self.len()
+    }
+
+    fn row_len(&self, a: usize) -> usize {
+	self[a].len()
+    }
+
+    fn entry(&self, a: usize, y: usize) -> f64 {
+	self[a][y].into()
+    }
+}
+
+/**
+ * The entries of the table. These are normally stored as f64, but may instead be
+ * stored as f32 to halve the memory used, each rounded down so that it is still a
+ * lower bound. This loses at most a relative 2^-24 (about 6e-8) of each entry,
+ * which is much less than the error DEFAULT_EPSILON allowed in the integration,
+ * but more than the EPSILON used when comparing against prob_cutoff.
+ */
+pub enum Entries {
+    Double(Vec<Vec<f64>>),
+    Single(Vec<Vec<f32>>),
+}
+
+impl Table for Entries {
+    fn num_rows(&self) -> usize {
+	match self {
+	    Entries::Double(bounds) => bounds.num_rows(),
+	    Entries::Single(bounds) => bounds.num_rows(),
+	}
+    }
+
+    fn row_len(&self, a: usize) -> usize {
+	match self {
+	    Entries::Double(bounds) => bounds.row_len(a),
+	    Entries::Single(bounds) => bounds.row_len(a),
+	}
+    }
+
+    fn entry(&self, a: usize, y: usize) -> f64 {
+	match self {
+	    Entries::Double(bounds) => bounds.entry(a, y),
+	    Entries::Single(bounds) => bounds.entry(a, y),
+	}
+    }
+}
+
+/**
+ * The largest f32 which is at most x.
+ */
+fn round_down_f32(x: f64) -> f32 {
+    let rounded = x as f32;
+    if rounded as f64 > x {
+	rounded.next_down()
+    } else {
+	rounded
+    }
+}
+
+/**
+ * The candidates in precomputation #2's update of a single entry of the table,
+ * at threshold t: the trivial bound, the two halves of the bound from eliminating
+ * the largest coefficient (if allowed), and the entry for the next smaller a_1
+ * (if any). The new value is the larger of the first two, capped by the last.
+ */
+struct CellUpdate {
+    t: f64,
+    trivial: f64,
+    elimination: Option<(f64, f64)>,
+    smaller_a: Option<f64>,
+    value: f64,
+}
+
+/**
+ * bounds[a][y] is a lower bound on Pr[X > t] where the threshold t is
+ * (y - max_bound) / thresh_granularity, so the table covers both negative and
+ * non-negative thresholds.
+ * Note that the negative half cannot be recovered from the non-negative half by
+ * the symmetry Pr[X > -t] = 1 - Pr[X >= t], as that would require an upper bound
+ * on Pr[X >= t], whereas the table only stores lower bounds. The best that
+ * symmetry gives is Pr[X > -t] >= 1/2, which is weaker than the stored values.
+ */
 pub struct Bounder {
-    bounds: Vec<Vec<f64>>,
+    bounds: Entries,
     coef_granularity: usize,
     thresh_granularity: usize,
     max_bound: usize,
+    // Whether get has been asked for a value beyond the top of the table.
+    warned_beyond_table: AtomicBool,
+    // The table transposed, built the first time a column is asked for, as sweeps
+    // over a at a fixed threshold would otherwise stride across every row.
+    columns: OnceLock<Vec<Vec<f64>>>,
 }
 
 impl Bounder {
@@ -143,40 +463,161 @@ impl Bounder {
      * A bug was fixed here during the translation from python, wherein negative
      * values of cutoff were rounded the wrong way.
      */
-    fn get_internal(bounds: &Vec<Vec<f64>>, coef_granularity: usize,
+    fn get_internal(bounds: &impl Table, coef_granularity: usize,
 		    thresh_granularity: usize, max_bound: usize, a: f64,
 		    cutoff: f64) -> f64 {
-        // A[M-1] represents a_1 = 1 case.
-        let a_scaled = ((a * coef_granularity as f64).ceil() as usize)
-	    .min(bounds.len() - 1);
-        let cutoff_scaled = (((cutoff * (thresh_granularity as f64))
-			      + max_bound as f64).ceil() as usize).max(0);
+	let (a_scaled, cutoff_scaled) = Self::get_indices(bounds, coef_granularity,
+							  thresh_granularity, max_bound,
+							  a, cutoff);
         // A clear lower bound
-        if cutoff_scaled >= bounds[a_scaled].len() {
+	if cutoff_scaled >= bounds.row_len(a_scaled) {
             0.0
         } else {
-            bounds[a_scaled][cutoff_scaled]
+	    bounds.entry(a_scaled, cutoff_scaled)
         }
     }
 
-    pub fn new_manual(bounds: Vec<Vec<f64>>, coef_granularity: usize,
+    /**
+     * The entry of the table which get_internal uses for the given a and cutoff.
+     * The cutoff index may be beyond the end of the table.
+     */
+    fn get_indices(bounds: &impl Table, coef_granularity: usize,
+		   thresh_granularity: usize, max_bound: usize, a: f64,
+		   cutoff: f64) -> (usize, usize) {
+	// A[M-1] represents a_1 = 1 case. After rescaling by the remaining variance
+	// a may be larger than 1, but no coefficient can exceed the standard
+	// deviation, so this is the same as a = 1.
+	let a_scaled = ((a * coef_granularity as f64).ceil() as usize)
+	    .min(bounds.num_rows() - 1);
+	(a_scaled, Self::index_of_threshold(thresh_granularity, max_bound, cutoff))
+    }
+
+    /**
+     * The largest cutoff for which get_internal uses index y of a row, so that
+     * bounds[a][y] must be a lower bound on Pr[X > threshold_of_index(y)]. The
+     * precomputations in fact bound Pr[X >= threshold_of_index(y + 1)], one step
+     * further on, which is smaller.
+     */
+    fn threshold_of_index(thresh_granularity: usize, max_bound: usize, y: usize) -> f64 {
+	(y as f64 - max_bound as f64) / thresh_granularity as f64
+    }
+
+    /**
+     * The index of a row which get_internal uses for the given cutoff, which may be
+     * beyond the end of the table: the smallest y with cutoff at most
+     * threshold_of_index(y). A cutoff on the grid can come out a rounding error
+     * above its index once scaled, which would give the next index, so we snap to
+     * the grid first. As each entry bounds the probability a whole step further
+     * on, this is still a valid lower bound.
+     */
+    fn index_of_threshold(thresh_granularity: usize, max_bound: usize, cutoff: f64) -> usize {
+	const GRID_SNAP: f64 = 1e-9;
+	let scaled = cutoff * thresh_granularity as f64 + max_bound as f64;
+	let nearest = scaled.round();
+	if (scaled - nearest).abs() < GRID_SNAP {
+	    nearest as usize
+	} else {
+	    scaled.ceil() as usize
+	}
+    }
+
+    /**
+     * As threshold_of_index, for this table.
+     */
+    pub fn threshold_of(&self, y: usize) -> f64 {
+	Self::threshold_of_index(self.thresh_granularity, self.max_bound, y)
+    }
+
+    /**
+     * As index_of_threshold, for this table. This is the inverse of threshold_of.
+     */
+    pub fn y_of(&self, cutoff: f64) -> usize {
+	Self::index_of_threshold(self.thresh_granularity, self.max_bound, cutoff)
+    }
+
+    pub fn new_manual(bounds: Entries, coef_granularity: usize,
 		      thresh_granularity: usize, max_bound: usize) -> Bounder {
-        Bounder { bounds, coef_granularity, thresh_granularity, max_bound }
+	Bounder { bounds, coef_granularity, thresh_granularity, max_bound,
+		  warned_beyond_table: AtomicBool::new(false), columns: OnceLock::new() }
     }
 
     pub fn header_line(&self) -> String {
-        format!("{},{},{}", self.coef_granularity, self.thresh_granularity,
-		self.max_bound)
+	let storage = match self.bounds {
+	    Entries::Double(_) => "",
+	    Entries::Single(_) => ",f32",
+	};
+	format!("{},{},{}{}", self.coef_granularity, self.thresh_granularity,
+		self.max_bound, storage)
     }
 
-    pub fn bounds(&self) -> &Vec<Vec<f64>> {
+    pub fn bounds(&self) -> &Entries {
         &self.bounds
     }
 
-    pub fn new() -> Bounder {
-        let coef_granularity = N;
-        let thresh_granularity = N;
-        let max_bound = 3 * thresh_granularity;
+    /**
+     * Converts the table to be stored as f32, rounding each entry down. Returns the
+     * largest amount by which any entry decreased.
+     */
+    pub fn store_as_single(&mut self) -> f64 {
+	let mut max_loss: f64 = 0.0;
+	if let Entries::Double(bounds) = &self.bounds {
+	    let single = bounds.iter().map(|row| row.iter().map(|x| {
+		let rounded = round_down_f32(*x);
+		max_loss = max_loss.max(x - rounded as f64);
+		rounded
+	    }).collect()).collect();
+	    self.bounds = Entries::Single(single);
+	    self.columns = OnceLock::new();
+	}
+	max_loss
+    }
+
+    /**
+     * The table as f64, which precomputations #1 and #2 work with.
+     */
+    fn double_mut(&mut self) -> &mut Vec<Vec<f64>> {
+	// The caller may change the entries, so any transpose is now stale.
+	self.columns = OnceLock::new();
+	match &mut self.bounds {
+	    Entries::Double(bounds) => bounds,
+	    Entries::Single(_) => panic!("Precomputation needs the table stored as f64!"),
+	}
+    }
+
+    /**
+     * A 64-bit FNV-1a hash of the header and of the exact bits of every entry, so
+     * that results can be tied to the table which produced them. Since the table
+     * is saved with enough digits to read back every entry exactly, this is the
+     * same before and after a round trip through bounder.csv.
+     */
+    pub fn fingerprint(&self) -> u64 {
+	const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+	const FNV_PRIME: u64 = 0x100000001b3;
+	let header = self.header_line();
+	let entries = (0..self.bounds.num_rows())
+	    .flat_map(|a| (0..self.bounds.row_len(a)).map(move |y| (a, y)))
+	    .flat_map(|(a, y)| self.bounds.entry(a, y).to_bits().to_le_bytes());
+	header.bytes().chain(entries).fold(FNV_OFFSET, |hash, byte| {
+	    (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+	})
+    }
+
+    /**
+     * Precomputation #1: fills the table directly from the Prawitz bound. This is
+     * the expensive part of generation, and does not depend on D_ITERATIONS.
+     * Here q is the split point used in compute_f, as a fraction.
+     */
+    pub fn fill_base(q: (usize, usize)) -> Bounder {
+	Self::fill_base_sized(q, N, N)
+    }
+
+    /**
+     * As fill_base, but for a table of the given resolution, such as a small one
+     * for testing.
+     */
+    pub fn fill_base_sized(q: (usize, usize), coef_granularity: usize,
+			   thresh_granularity: usize) -> Bounder {
+	let max_bound = THRESHOLD_RANGE * thresh_granularity;
         let mut bounds = vec![vec![0.0; 2 * max_bound]; coef_granularity];
 
         print!("Precomputation #1, {} steps: ", 2 * max_bound);
@@ -185,131 +626,1145 @@ impl Bounder {
                 print!("{}% ", (y * 100) / (2 * max_bound));
                 let _ = io::stdout().flush();
             }
-            for a in 0..coef_granularity {
+	    // Each entry is independent, so we fill this column in parallel.
+	    bounds.par_iter_mut().enumerate().for_each(|(a, row)| {
                 // The round-up is a (pessimistic) speedup. To allow caching.
-                bounds[a][y] = prawitz_bound_raw(a, y, coef_granularity,
-						 thresh_granularity, max_bound);
+		row[y] = prawitz_bound_raw(a, y, coef_granularity,
+					   thresh_granularity, max_bound, q);
                 // If threshold < 0, then Pr[X > threshold] >= 1/2.
                 if y < max_bound {
-                    bounds[a][y] = bounds[a][y].max(0.5)
+		    row[y] = row[y].max(0.5)
                 }
-            }
+	    });
         }
-
         println!();
-        print!("Precomputation #2, {} steps: ", D_ITERATIONS);
 
-        for i in 0..D_ITERATIONS {
+	Bounder::new_manual(Entries::Double(bounds), coef_granularity, thresh_granularity,
+			    max_bound)
+    }
+
+    /**
+     * The update which precomputation #2 makes to bounds[a][y], from the rest of
+     * the table as it stands. If gaussian is set, then the remainder left after
+     * eliminating a_1 is also bounded below by the Gaussian floor where that applies.
+     */
+    fn update_cell(bounds: &impl Table, coef_granularity: usize, thresh_granularity: usize,
+		   max_bound: usize, a: usize, y: usize, gaussian: bool) -> CellUpdate {
+	// The threshold we consider.
+	let t = Self::threshold_of_index(thresh_granularity, max_bound, y + 1);
+	// In bounds[a][y+max_bound] we assign a lower bound to Pr[X >= t],
+	// given a_1 <= (a+1)/coef_granularity.
+	// We split into two cases:
+	//   a_1 <= a/coef_granularity
+	//   a_1 in [a/coef_granularity, (a+1)/coef_granularity]
+	// The first case may be lower bounded using bounds[a-1, y+max_bound].
+	// The second case is lower bounded by elimination
+	// of largest coefficient, and trivial bounds.
+	// The lower bound is the minimum of the two cases.
+	//
+	// We start with the second case:
+	let min_a_1 = a as f64 / coef_granularity as f64;
+	let max_a_1 = (a as f64 + 1.0) / coef_granularity as f64;
+	// minimum variance of a_2 * epsilon_2 + ... + a_n * epsilon_n
+	let min_sigma = (1.0 - max_a_1.powi(2)).powf(0.5);
+	// if t <= a_1, clearly Pr[X >= t]
+	// is lower bounded by 1/4:
+	// the Rademacher sum is larger than t whenever both
+	//   sign of a_1 is positive (probability 1/2)
+	//   sign of the rest of the process is positive (probability >= 1/2)
+	let trivial: f64 = if t <= min_a_1 { 0.25 } else { 0.0 };
+	// Note that the case a = coef_granularity-1 which includes a_1 = 1,
+	// for which elimination is prohibited, is handled correctly.
+	let elimination = (a + 1 < coef_granularity).then(|| {
+	    let sta1 = Self::get_internal(bounds, coef_granularity, thresh_granularity,
+					  max_bound, max_a_1 / min_sigma,
+					  (t - min_a_1) / min_sigma);
+	    let sta2 = Self::get_internal(bounds, coef_granularity, thresh_granularity,
+					  max_bound, max_a_1 / min_sigma,
+					  (t + max_a_1) / min_sigma);
+	    if gaussian && max_a_1 / min_sigma < GAUSSIAN_FLOOR_MAX_A {
+		// The remainder has standard deviation between min_sigma and
+		// max_sigma, and we rescale each threshold by whichever of these
+		// makes it largest, as the floor may be used on either side of 0.
+		let max_sigma = (1.0 - min_a_1.powi(2)).powf(0.5);
+		let scale = |cutoff: f64| if cutoff >= 0.0 {
+		    cutoff / min_sigma
+		} else {
+		    cutoff / max_sigma
+		};
+		(sta1.max(get_gaussian(max_a_1 / min_sigma, scale(t - min_a_1))),
+		 sta2.max(get_gaussian(max_a_1 / min_sigma, scale(t + max_a_1))))
+	    } else {
+		(sta1, sta2)
+	    }
+	});
+	// We now consider the case a_1 <= a / coef_granularity,
+	// and take the minimum.
+	let smaller_a = (a > 0).then(|| bounds.entry(a - 1, y));
+	let mut value = trivial;
+	if let Some((sta1, sta2)) = elimination {
+	    value = value.max((sta1 + sta2) / 2.0);
+	}
+	if let Some(smaller_a) = smaller_a {
+	    value = value.min(smaller_a);
+	}
+	CellUpdate { t, trivial, elimination, smaller_a, value }
+    }
+
+    /**
+     * Prints each of the candidates in the update which precomputation #2 makes to
+     * bounds[a][y], and which of them determines it, to show why the entry has the
+     * value it does. Here gaussian is as in relax.
+     */
+    pub fn trace_cell(&self, a: usize, y: usize, gaussian: bool) {
+	let update = Self::update_cell(&self.bounds, self.coef_granularity,
+				       self.thresh_granularity, self.max_bound, a, y, gaussian);
+	let current = self.bounds.entry(a, y);
+	println!("bounds[{}][{}] = {}, for a_1 <= {} and threshold {}", a, y, current,
+		 (a as f64 + 1.0) / self.coef_granularity as f64, update.t);
+	println!("  trivial bound: {}", update.trivial);
+	match update.elimination {
+	    Some((sta1, sta2)) => println!("  elimination of a_1: ({} + {}) / 2 = {}",
+					   sta1, sta2, (sta1 + sta2) / 2.0),
+	    None => println!("  elimination of a_1: not allowed, as a_1 may be 1"),
+	}
+	match update.smaller_a {
+	    Some(smaller_a) => println!("  bounds[{}][{}] (smaller a_1): {}", a - 1, y, smaller_a),
+	    None => println!("  bounds[a-1][y] (smaller a_1): none, as a = 0"),
+	}
+	let eliminated = update.elimination.map(|(sta1, sta2)| (sta1 + sta2) / 2.0);
+	let uncapped = update.trivial.max(eliminated.unwrap_or(0.0));
+	let winner = if update.smaller_a.is_some_and(|smaller_a| smaller_a < uncapped) {
+	    "entry for smaller a_1"
+	} else if eliminated.is_some_and(|eliminated| eliminated > update.trivial) {
+	    "elimination of a_1"
+	} else {
+	    "trivial bound"
+	};
+	println!("  update gives {}, from the {}", update.value, winner);
+	if current > update.value {
+	    println!("  the entry is larger than this, so it comes from precomputation #1 or a warm start");
+	}
+    }
+
+    /**
+     * Precomputation #2: the fixed-point relaxation, run for the given number of
+     * iterations on top of the existing table. If snapshot_every is given, then
+     * every that many iterations the table so far is passed to snapshot, along
+     * with the number of iterations done.
+     * If gaussian is set, then the elimination of a_1 may also use the Gaussian
+     * floor for the remainder, when its largest coefficient is small enough. This
+     * only ever raises the candidates, so the table is never weaker than without.
+     */
+    pub fn relax(&mut self, iters: usize, snapshot_every: Option<usize>, gaussian: bool,
+		 snapshot: impl Fn(&Bounder, usize)) {
+	let coef_granularity = self.coef_granularity;
+	let thresh_granularity = self.thresh_granularity;
+	let max_bound = self.max_bound;
+
+	print!("Precomputation #2, {} steps: ", iters);
+
+	for i in 0..iters {
             if i % 5 == 0 {
-                print!("{}% ", (i * 100) / D_ITERATIONS);
+		print!("{}% ", (i * 100) / iters);
 		let _ = io::stdout().flush();
             }
+	    let bounds = self.double_mut();
             for y in 0..(2 * max_bound) {
-                // The threshold we consider.
-                let t = (y as f64 - max_bound as f64 + 1.0) / thresh_granularity as f64;
                 for a in 0..coef_granularity {
-                    // In bounds[a][y+max_bound] we assign a lower bound to Pr[X >= t],
-                    // given a_1 <= (a+1)/coef_granularity.
-                    // We split into two cases:
-                    //   a_1 <= a/coef_granularity
-                    //   a_1 in [a/coef_granularity, (a+1)/coef_granularity]
-                    // The first case may be lower bounded using bounds[a-1, y+max_bound].
-                    // The second case is lower bounded by elimination
-                    // of largest coefficient, and trivial bounds.
-                    // The lower bound is the minimum of the two cases.
-                    //
-                    // We start with the second case:
-                    let min_a_1 = a as f64 / coef_granularity as f64;
-                    let max_a_1 = (a as f64 + 1.0) / coef_granularity as f64;
-                    // minimum variance of a_2 * epsilon_2 + ... + a_n * epsilon_n
-                    let min_sigma = (1.0 - max_a_1.powi(2)).powf(0.5);
-                    // if t <= a_1, clearly Pr[X >= t]
-                    // is lower bounded by 1/4:
-                    // the Rademacher sum is larger than t whenever both
-                    //   sign of a_1 is positive (probability 1/2)
-                    //   sign of the rest of the process is positive (probability >= 1/2)
-                    let mut bound: f64 = if t <= min_a_1 { 0.25 } else { 0.0 };
-                    // Note that the case a = coef_granularity-1 which includes a_1 = 1,
-                    // for which elimination is prohibited, is handled correctly.
-                    if a+1 < coef_granularity {
-                        let sta1 = Self::get_internal(&bounds, coef_granularity,
-						      thresh_granularity, max_bound,
-                            max_a_1 / min_sigma, (t - min_a_1) / min_sigma);
-                        let sta2 = Self::get_internal(&bounds, coef_granularity,
-						      thresh_granularity, max_bound,
-						      max_a_1 / min_sigma,
-						      (t + max_a_1) / min_sigma);
-                        bound = bound.max((sta1 + sta2) / 2.0);
-                    }
-                    // We now consider the case a_1 <= a / coef_granularity,
-		    // and take the minimum.
-                    if a > 0 {
-                        bound = bound.min(bounds[a - 1][y]);
-                    }
+		    let update = Self::update_cell(&*bounds, coef_granularity,
+						   thresh_granularity, max_bound, a, y, gaussian);
                     // If we got a better lower bound to bounds[a][y], we update it.
-                    if bound > bounds[a][y] {
-                        bounds[a][y] = bound;
+		    if update.value > bounds[a][y] {
+			bounds[a][y] = update.value;
                     }
                 }
             }
+	    if snapshot_every.is_some_and(|every| (i + 1).is_multiple_of(every)) {
+		snapshot(self, i + 1);
+	    }
         }
 
         println!();
+    }
 
-        Bounder { bounds, coef_granularity, thresh_granularity, max_bound }
+    /**
+     * Raises each entry of the table to the bound given by another table, which may
+     * have a different resolution, to give precomputation #2 a head start. We look
+     * up the other table at the largest a and smallest threshold which this entry
+     * covers, and since get_internal rounds both upwards this is still a valid
+     * lower bound. Bilinear interpolation would converge faster still, but would
+     * not be.
+     * Starting from a higher table, precomputation #2 may converge to a higher
+     * fixed point than it would from scratch, but never a lower one.
+     */
+    pub fn warm_start(&mut self, other: &Bounder) {
+	let coef_granularity = self.coef_granularity;
+	let thresh_granularity = self.thresh_granularity;
+	let max_bound = self.max_bound;
+	for (a, row) in self.double_mut().iter_mut().enumerate() {
+	    let max_a_1 = (a as f64 + 1.0) / coef_granularity as f64;
+	    for (y, entry) in row.iter_mut().enumerate() {
+		let t = Self::threshold_of_index(thresh_granularity, max_bound, y + 1);
+		let other_bound = Self::get_internal(&other.bounds, other.coef_granularity,
+						     other.thresh_granularity, other.max_bound,
+						     max_a_1, t);
+		*entry = entry.max(other_bound);
+	    }
+	}
     }
 
     /**
-     * Returns our best lower bound on the function P(X > cutoff)
+     * Returns our best lower bound on the function P(X > cutoff). Beyond the top
+     * of the table this is trivially 0, which we warn about the first time it
+     * happens, as it may be why a case cannot be proved.
      */
     pub fn get(&self, a: f64, cutoff: f64) -> f64 {
-        /**
-         * Bernstein's inequality; from 
-	 * https://en.wikipedia.org/wiki/Bernstein_inequalities_(probability_theory)
-         * (first one in 'some of the inequalities' section.)
-         */
-        fn get_bernstein(a: f64, t: f64) -> f64 {
-            1.0 - ((- (t * t)) / (2.0 * (1.0 - (a * t / 3.0)))).exp()
-        }
+	self.get_with_bernstein(a, cutoff, true)
+    }
 
-        let d = Self::get_internal(&self.bounds, self.coef_granularity,
+    /**
+     * As get, but if use_bernstein is false then neither Bernstein's inequality
+     * nor the Chernoff bound is considered, leaving the table and the Gaussian
+     * floor. As the Chernoff bound is never below Bernstein's inequality, turning
+     * off Bernstein's inequality alone would change nothing. This can only lower
+     * the bound.
+     */
+    pub fn get_with_bernstein(&self, a: f64, cutoff: f64, use_bernstein: bool) -> f64 {
+	let explanation = self.explain_with_bernstein(a, cutoff, use_bernstein);
+	if explanation.source == BoundSource::BeyondTable
+	    && !self.warned_beyond_table.swap(true, Ordering::Relaxed) {
+		println!("WARNING: D({}, {}) is beyond the top of the table at {}, so is taken to be 0. Further such values will not be reported.",
+			 a, cutoff, self.threshold_range().1);
+	    }
+	explanation.value
+    }
+
+    /**
+     * Computes get(a, cutoff), keeping track of where the value came from.
+     */
+    pub fn explain(&self, a: f64, cutoff: f64) -> Explanation {
+	self.explain_with_bernstein(a, cutoff, true)
+    }
+
+    fn explain_with_bernstein(&self, a: f64, cutoff: f64, use_bernstein: bool) -> Explanation {
+	let (a_index, cutoff_index) = Self::get_indices(&self.bounds, self.coef_granularity,
+							self.thresh_granularity,
+							self.max_bound, a, cutoff);
+	let table_value = Self::get_internal(&self.bounds, self.coef_granularity,
             self.thresh_granularity, self.max_bound, a, cutoff);
-        if cutoff < -3.0 {
-            d.max(get_bernstein(a, cutoff))
+	let bernstein_value = if use_bernstein && cutoff < BERNSTEIN_CUTOFF {
+	    Some(get_bernstein(a, cutoff))
+	} else {
+	    None
+	};
+	let chernoff_value = if use_bernstein { get_chernoff(cutoff) } else { 0.0 };
+	let (mut value, mut source) = match bernstein_value {
+	    Some(bernstein) if bernstein > table_value => (bernstein, BoundSource::Bernstein),
+	    _ if cutoff_index >= self.bounds.row_len(a_index) => (table_value, BoundSource::BeyondTable),
+	    _ => (table_value, BoundSource::Table),
+	};
+	if chernoff_value > value {
+	    (value, source) = (chernoff_value, BoundSource::Chernoff);
+	}
+	let gaussian_value = (a < GAUSSIAN_FLOOR_MAX_A).then(|| get_gaussian(a, cutoff));
+	if let Some(gaussian) = gaussian_value.filter(|gaussian| *gaussian > value) {
+	    (value, source) = (gaussian, BoundSource::Gaussian);
+	}
+	Explanation { value, source, a_index, cutoff_index, table_value, bernstein_value,
+		      chernoff_value, gaussian_value }
+    }
+
+    /**
+     * Prints get(a, cutoff) and how it was derived. If the remaining variance is
+     * given as well, then first shows how get_with_var rescales a and cutoff.
+     */
+    pub fn print_explanation(&self, a: f64, cutoff: f64, var_range: Option<(f64, f64)>) {
+	let (a, cutoff) = match var_range {
+	    Some((min_var, max_var)) => {
+		match Self::scale_for_var(a, cutoff, min_var, max_var) {
+		    Some((scaled_a, scaled_cutoff)) => {
+			println!("Remaining variance in [{}, {}], so we rescale to D({}, {})",
+				 min_var, max_var, scaled_a, scaled_cutoff);
+			(scaled_a, scaled_cutoff)
+		    }
+		    None => {
+			println!("No remaining variance and cutoff >= 0, so the bound is trivially 0.");
+			return;
+		    }
+		}
+	    }
+	    None => (a, cutoff),
+	};
+	let explanation = self.explain(a, cutoff);
+	println!("D({}, {}) = {}", a, cutoff, explanation.value);
+	if explanation.cutoff_index >= self.bounds.row_len(explanation.a_index) {
+	    println!("  table: bounds[{}][{}] is beyond the end of the table, so 0",
+		     explanation.a_index, explanation.cutoff_index);
         } else {
-            d
+	    println!("  table: bounds[{}][{}] = {}", explanation.a_index,
+		     explanation.cutoff_index, explanation.table_value);
+	    if cutoff < 0.0 {
+		println!("  (as the cutoff is negative, this includes the floor of 0.5)");
+	    }
+	}
+	match explanation.bernstein_value {
+	    Some(bernstein) => println!("  Bernstein: {}", bernstein),
+	    None => println!("  Bernstein: not used, as cutoff >= {}", BERNSTEIN_CUTOFF),
         }
+	println!("  Chernoff: {}", explanation.chernoff_value);
+	match explanation.gaussian_value {
+	    Some(gaussian) => println!("  Gaussian: {}", gaussian),
+	    None => println!("  Gaussian: not used, as a >= {}", GAUSSIAN_FLOOR_MAX_A),
+	}
+	println!("  source: {:?}", explanation.source);
+    }
+
+    /**
+     * Returns an upper bound on P(X > cutoff), to go with the lower bound from get.
+     * By symmetry P(X <= cutoff) = P(X >= -cutoff) >= get(a, -cutoff), and for
+     * cutoff >= 0 we also have Hoeffding's inequality and P(X > cutoff) <= 1/2.
+     */
+    pub fn get_upper(&self, a: f64, cutoff: f64) -> f64 {
+	let symmetric = 1.0 - self.get(a, -cutoff);
+	if cutoff >= 0.0 {
+	    symmetric.min(get_hoeffding(cutoff)).min(0.5)
+	} else {
+	    symmetric
+	}
+    }
+
+    /**
+     * Prints the lower and upper bounds on P(X > cutoff), and the gap between them.
+     */
+    pub fn print_sandwich(&self, a: f64, cutoff: f64) {
+	let lower = self.get(a, cutoff);
+	let upper = self.get_upper(a, cutoff);
+	println!("{} <= P(X > {}) <= {} for max a_i <= {}", lower, cutoff, upper, a);
+	println!("gap: {}", upper - lower);
+	if lower > upper {
+	    println!("ERROR: the lower bound is above the upper bound, so the table is invalid!");
+	}
     }
 
     pub fn print(&self, a: f64, cutoff: f64) {
         let val = self.get(a, cutoff);
-        let a_scaled = ((a * self.coef_granularity as f64) as usize)
-	    .min(self.bounds.len() - 1);
-        let cutoff_scaled = ((cutoff * self.thresh_granularity as f64) as usize
-			     + self.max_bound).max(0);
+	let (a_scaled, cutoff_scaled) = Self::get_indices(&self.bounds, self.coef_granularity,
+							  self.thresh_granularity,
+							  self.max_bound, a, cutoff);
         println!("D({}, {}) ~ bounds[{}][{}] = {}", a, cutoff, a_scaled,
 		 cutoff_scaled, val);
     }
 
+    /**
+     * The range of cutoffs which are represented in the table. Beyond the top of
+     * this range the bound is trivially 0, and below the bottom we rely on the
+     * first column of the table and Bernstein's inequality.
+     */
+    pub fn threshold_range(&self) -> (f64, f64) {
+	(self.threshold_of(0), self.threshold_of(2 * self.max_bound - 1))
+    }
+
+    /**
+     * Whether a case with the given threshold is close enough to the top of the
+     * range of cutoffs that most of the values it looks up are beyond it.
+     */
+    pub fn is_near_top(&self, threshold: f64) -> bool {
+	threshold > self.threshold_range().1 - NEAR_TOP_OF_TABLE
+    }
+
+    /**
+     * Prints D(a, x) for x from x0 to x1 in increments of step, stopping at the
+     * top of the range of cutoffs which the table represents.
+     */
+    pub fn print_range(&self, a: f64, x0: f64, x1: f64, step: f64) {
+	let (_min_cutoff, max_cutoff) = self.threshold_range();
+	if x1 > max_cutoff {
+	    println!("Clamping to the top of the range of the table: {}", max_cutoff);
+	}
+	let x1 = x1.min(max_cutoff);
+	let steps = ((x1 - x0) / step + 1e-9).floor();
+	if steps >= 0.0 {
+	    for i in 0..=(steps as usize) {
+		let x = x0 + i as f64 * step;
+		let explanation = self.explain(a, x);
+		match explanation.source {
+		    BoundSource::Bernstein | BoundSource::Chernoff | BoundSource::Gaussian =>
+			println!("D({}, {}) = {} ({:?})", a, x, explanation.value,
+				 explanation.source),
+		    _ => println!("D({}, {}) = {}", a, x, explanation.value),
+		}
+	    }
+	}
+    }
+
+    /**
+     * Prints the range of cutoffs for which get_internal uses index y of a row, and
+     * the threshold at which the precomputations bound the probability there.
+     */
+    pub fn print_threshold_of(&self, y: usize) {
+	let upper = self.threshold_of(y);
+	if y == 0 {
+	    println!("Index 0 is used for every cutoff up to {}", upper);
+	} else {
+	    println!("Index {} is used for cutoffs in ({}, {}]", y, self.threshold_of(y - 1), upper);
+	}
+	println!("Its entries bound Pr[X >= {}] from below, which is at most Pr[X > {}]",
+		 self.threshold_of(y + 1), upper);
+	if y >= 2 * self.max_bound {
+	    println!("This is beyond the end of the table, where the bound is 0.");
+	}
+    }
+
+    /**
+     * The indices y of the cutoffs on the grid of the table at which D(a, cutoff) < p,
+     * that is, where the table cannot certify probability p.
+     */
+    fn below(&self, a: f64, p: f64) -> Vec<usize> {
+	(0..2 * self.max_bound)
+	    .filter(|y| self.explain(a, self.threshold_of(*y)).value < p)
+	    .collect()
+    }
+
+    /**
+     * Prints each cutoff given by below, with consecutive such cutoffs printed
+     * together as a range.
+     */
+    pub fn print_below(&self, a: f64, p: f64) {
+	let below = self.below(a, p);
+	let mut runs: Vec<(usize, usize)> = vec![];
+	for y in below.iter().copied() {
+	    match runs.last_mut() {
+		Some((_, end)) if *end + 1 == y => *end = y,
+		_ => runs.push((y, y)),
+	    }
+	}
+	for (start, end) in runs {
+	    if start == end {
+		println!("D({}, {}) < {}", a, self.threshold_of(start), p);
+	    } else {
+		println!("D({}, x) < {} for x from {} to {}", a, p, self.threshold_of(start),
+			 self.threshold_of(end));
+	    }
+	}
+	println!("{} of {} cutoffs in the table are below {}", below.len(),
+		 2 * self.max_bound, p);
+    }
+
+    /**
+     * How much this table improves on a reference table of the same resolution,
+     * where a larger entry is a better lower bound, or None if the resolutions
+     * differ. The differences are integrated over the grid, each entry standing
+     * for a cell of width 1/coef_granularity in a and 1/thresh_granularity in the
+     * cutoff, and the gains and losses are kept separately along with the worst loss.
+     */
+    fn defect(&self, reference: &Bounder) -> Option<Defect> {
+	if (self.coef_granularity, self.thresh_granularity, self.max_bound)
+	    != (reference.coef_granularity, reference.thresh_granularity, reference.max_bound) {
+		return None;
+	    }
+	let cell_area = 1.0 / (self.coef_granularity * self.thresh_granularity) as f64;
+	let mut defect = Defect { improved: 0, regressed: 0, unchanged: 0, gains: 0.0,
+				  losses: 0.0, worst: None };
+	for a in 0..self.bounds.num_rows() {
+	    for y in 0..self.bounds.row_len(a) {
+		let difference = self.bounds.entry(a, y) - reference.bounds.entry(a, y);
+		if difference > 0.0 {
+		    defect.improved += 1;
+		    defect.gains += difference * cell_area;
+		} else if difference < 0.0 {
+		    defect.regressed += 1;
+		    defect.losses -= difference * cell_area;
+		    if defect.worst.is_none_or(|(_a, _y, worst)| difference < worst) {
+			defect.worst = Some((a, y, difference));
+		    }
+		} else {
+		    defect.unchanged += 1;
+		}
+	    }
+	}
+	Some(defect)
+    }
+
+    /**
+     * Prints the defect of this table against a reference table.
+     */
+    pub fn print_defect(&self, reference: &Bounder) {
+	let Some(defect) = self.defect(reference) else {
+	    println!("The reference table has resolution {}, but this one has {}!",
+		     reference.header_line(), self.header_line());
+	    return;
+	};
+	println!("{} entries improved, {} regressed and {} unchanged", defect.improved,
+		 defect.regressed, defect.unchanged);
+	println!("Net improvement integrated over the grid: {} (gains {}, losses {})",
+		 defect.gains - defect.losses, defect.gains, defect.losses);
+	match defect.worst {
+	    Some((a, y, difference)) => {
+		println!("Worst regression: D({}, {}) = {}, down by {} from {}",
+			 a as f64 / self.coef_granularity as f64, self.threshold_of(y),
+			 self.bounds.entry(a, y), -difference, reference.bounds.entry(a, y));
+	    }
+	    None => println!("No entry regressed."),
+	}
+    }
+
+    /**
+     * The entries bounds[a][y] for each a, that is, the column of the table at the
+     * threshold index y. The whole table is transposed the first time this is
+     * called, which takes as much memory again as the table itself.
+     */
+    pub fn column(&self, y: usize) -> &[f64] {
+	let columns = self.columns.get_or_init(|| {
+	    (0..self.bounds.row_len(0)).map(|y| {
+		(0..self.bounds.num_rows()).map(|a| self.bounds.entry(a, y)).collect()
+	    }).collect()
+	});
+	&columns[y]
+    }
+
+    /**
+     * The table should be non-increasing both in the cutoff and in a. Returns a
+     * list of (a, y, magnitude) for every violation of the first, and the same
+     * for the second.
+     */
+    pub fn monotonicity_violations(&self) -> (Violations, Violations) {
+	let mut in_cutoff = vec![];
+	let mut in_a = vec![];
+	let bounds = &self.bounds;
+	for a in 0..bounds.num_rows() {
+	    let row_len = bounds.row_len(a);
+	    for y in 0..row_len - 1 {
+		let (entry, next) = (bounds.entry(a, y), bounds.entry(a, y + 1));
+		if next > entry {
+		    in_cutoff.push((a, y, next - entry));
+		}
+	    }
+	}
+	// Along a we go by columns, so as not to stride across the rows.
+	for y in 0..bounds.row_len(0) {
+	    for (a, pair) in self.column(y).windows(2).enumerate() {
+		if pair[1] > pair[0] {
+		    in_a.push((a, y, pair[1] - pair[0]));
+		}
+	    }
+	}
+	in_a.sort_by_key(|(a, y, _)| (*a, *y));
+	(in_cutoff, in_a)
+    }
+
+    pub fn print_monotonicity(&self) {
+	const MAX_PRINTED: usize = 20;
+	let (in_cutoff, in_a) = self.monotonicity_violations();
+	for (violations, name, next) in [(in_cutoff, "cutoff", "bounds[a][y+1]"),
+					  (in_a, "a", "bounds[a+1][y]")] {
+	    if violations.is_empty() {
+		println!("Bounds are non-increasing in {}.", name);
+	    } else {
+		let worst = violations.iter().map(|x| x.2).fold(0.0, f64::max);
+		println!("{} violations of monotonicity in {}, the worst by {}:",
+			 violations.len(), name, worst);
+		for (a, y, magnitude) in violations.iter().take(MAX_PRINTED) {
+		    println!("  a = {}, y = {}: {} exceeds bounds[a][y] by {}",
+			     a, y, next, magnitude);
+		}
+		if violations.len() > MAX_PRINTED {
+		    println!("  ...");
+		}
+	    }
+	}
+    }
+
+    /**
+     * Note that under ZeroAfter(m) the coefficients from a_m onwards have numerator
+     * zero, which is the interval [0, 1/d] rather than exactly zero. So the range of
+     * remaining variance passed here still allows for them, rather than being
+     * exactly the complement of the variance of a_0, ..., a_{m-1}.
+     */
     pub fn get_with_var(&self, a: f64, cutoff: f64, min_remaining_var: f64,
 			max_remaining_var: f64) -> f64 {
+	self.get_with_method(BoundMethod::Full, true, a, cutoff, min_remaining_var,
+			     max_remaining_var)
+    }
+
+    /**
+     * As get_with_var, but only using the bounds allowed by method. Bernstein's
+     * inequality says nothing for cutoff >= 0, where the bound is then 0.
+     * With BoundMethod::Full, use_bernstein is as in get_with_bernstein.
+     */
+    pub fn get_with_method(&self, method: BoundMethod, use_bernstein: bool, a: f64, cutoff: f64,
+			   min_remaining_var: f64, max_remaining_var: f64) -> f64 {
+	match (Self::scale_for_var(a, cutoff, min_remaining_var, max_remaining_var), method) {
+	    (Some((a, cutoff)), BoundMethod::Full) => {
+		self.get_with_bernstein(a, cutoff, use_bernstein)
+	    }
+	    (Some((a, cutoff)), BoundMethod::Bernstein) if cutoff < 0.0 => {
+		get_bernstein(a, cutoff).max(0.0)
+	    }
+	    _ => 0.0,
+	}
+    }
+
+    /**
+     * Rescales a and cutoff to a process of variance 1, given the possible range of
+     * the remaining variance, in the way which gives the worst bound. Returns None
+     * if the bound is trivially 0.
+     */
+    fn scale_for_var(a: f64, cutoff: f64, min_remaining_var: f64,
+		     max_remaining_var: f64) -> Option<(f64, f64)> {
         if min_remaining_var > 0.0 {
             if cutoff >= 0.0 {
                 // Make cutoff as large in absolute value as possible
-                self.get(a / min_remaining_var.sqrt(), cutoff / min_remaining_var.sqrt())
+		Some((a / min_remaining_var.sqrt(), cutoff / min_remaining_var.sqrt()))
             } else {
                 // Make cutoff as small in absolute value as possible
-                self.get(a / min_remaining_var.sqrt(), cutoff / max_remaining_var.sqrt())
+		Some((a / min_remaining_var.sqrt(), cutoff / max_remaining_var.sqrt()))
             }
         } else {
             if cutoff >= 0.0 {
                 // Make cutoff as large as possible - in this case, infinite.
-                0.0
+		None
             } else {
                 // Make cutoff as small in absolute value as possible.
 		// a is infinite so rounds down to 1.
-                self.get(1.0, cutoff / max_remaining_var.sqrt())
+		Some((1.0, cutoff / max_remaining_var.sqrt()))
             }
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::util::gaussian_tail;
+
+    /**
+     * A coarse table, which is quick enough to build once and share between tests.
+     */
+    pub fn small_bounder() -> &'static Bounder {
+	static SMALL: OnceLock<Bounder> = OnceLock::new();
+	SMALL.get_or_init(|| {
+	    let mut bounder = Bounder::fill_base_sized(DEFAULT_Q, 50, 50);
+	    bounder.relax(D_ITERATIONS, None, false, |_bounder, _iteration| ());
+	    bounder
+	})
+    }
+
+    pub fn entries(bounder: &Bounder) -> Vec<Vec<f64>> {
+	(0..bounder.bounds.num_rows())
+	    .map(|a| (0..bounder.bounds.row_len(a)).map(|y| bounder.bounds.entry(a, y)).collect())
+	    .collect()
+    }
+
+    /**
+     * The table as Bounder::new built it before it was split into fill_base and
+     * relax, with both precomputations in one loop. The loops are kept as they
+     * were, indexing the table directly.
+     */
+    #[allow(clippy::needless_range_loop)]
+    fn reference_new(q: (usize, usize), coef_granularity: usize,
+		     thresh_granularity: usize) -> Vec<Vec<f64>> {
+	let max_bound = THRESHOLD_RANGE * thresh_granularity;
+	let mut bounds = vec![vec![0.0; 2 * max_bound]; coef_granularity];
+	for y in 0..(2 * max_bound) {
+	    for a in 0..coef_granularity {
+		bounds[a][y] = prawitz_bound_raw(a, y, coef_granularity, thresh_granularity,
+						 max_bound, q);
+		if y < max_bound {
+		    bounds[a][y] = bounds[a][y].max(0.5)
+		}
+	    }
+	}
+	for _ in 0..D_ITERATIONS {
+	    for y in 0..(2 * max_bound) {
+		let t = (y as f64 - max_bound as f64 + 1.0) / thresh_granularity as f64;
+		for a in 0..coef_granularity {
+		    let min_a_1 = a as f64 / coef_granularity as f64;
+		    let max_a_1 = (a as f64 + 1.0) / coef_granularity as f64;
+		    let min_sigma = (1.0 - max_a_1.powi(2)).powf(0.5);
+		    let mut bound: f64 = if t <= min_a_1 { 0.25 } else { 0.0 };
+		    if a + 1 < coef_granularity {
+			let sta1 = Bounder::get_internal(&bounds, coef_granularity,
+							 thresh_granularity, max_bound,
+							 max_a_1 / min_sigma, (t - min_a_1) / min_sigma);
+			let sta2 = Bounder::get_internal(&bounds, coef_granularity,
+							 thresh_granularity, max_bound,
+							 max_a_1 / min_sigma, (t + max_a_1) / min_sigma);
+			bound = bound.max((sta1 + sta2) / 2.0);
+		    }
+		    if a > 0 {
+			bound = bound.min(bounds[a - 1][y]);
+		    }
+		    if bound > bounds[a][y] {
+			bounds[a][y] = bound;
+		    }
+		}
+	    }
+	}
+	bounds
+    }
+
+    #[test]
+    fn fill_base_then_relax_matches_new() {
+	let mut bounder = Bounder::fill_base_sized(DEFAULT_Q, 20, 10);
+	bounder.relax(D_ITERATIONS, None, false, |_bounder, _iteration| ());
+	assert_eq!(entries(&bounder), reference_new(DEFAULT_Q, 20, 10));
+    }
+
+    #[test]
+    fn split_point_changes_the_bound() {
+	let (a_num, a_denom, x_num, x_denom) = (1, 2, 1, 4);
+	let default = prawitz_bound(a_num, a_denom, x_num, x_denom, DEFAULT_Q.0, DEFAULT_Q.1);
+	let half = q_as_fraction(0.5).unwrap();
+	assert_eq!(prawitz_bound(a_num, a_denom, x_num, x_denom, half.0, half.1), default);
+	let others = [0.2, 0.35].iter().map(|q| {
+	    let (q_num, q_denom) = q_as_fraction(*q).unwrap();
+	    prawitz_bound(a_num, a_denom, x_num, x_denom, q_num, q_denom)
+	}).collect::<Vec<f64>>();
+	assert!(others.iter().all(|bound| *bound != default && *bound >= 0.0));
+	assert_eq!(q_as_fraction(0.6), None);
+    }
+
+    #[test]
+    fn small_coefficients_stay_below_the_gaussian_tail() {
+	let bounder = small_bounder();
+	for x in [-2.0, -1.0, -0.3, 0.0, 0.3, 1.0, 2.0] {
+	    assert!(bounder.get(0.001, x) <= gaussian_tail(x),
+		    "D(0.001, {}) = {} exceeds the Gaussian tail {}", x, bounder.get(0.001, x),
+		    gaussian_tail(x));
+	}
+    }
+
+    /**
+     * Pr[X > t] for X the sum of n Rademacher variables each with coefficient
+     * 1/sqrt(n), summing the binomial probabilities in log space.
+     */
+    fn equal_coefficients_tail(n: usize, t: f64) -> f64 {
+	let mut log_choose = 0.0;
+	let mut tail = 0.0;
+	for k in 0..=n {
+	    if k > 0 {
+		log_choose += ((n - k + 1) as f64 / k as f64).ln();
+	    }
+	    if (2.0 * k as f64 - n as f64) / (n as f64).sqrt() > t {
+		tail += (log_choose - n as f64 * 2f64.ln()).exp();
+	    }
+	}
+	tail
+    }
+
+    #[test]
+    fn gaussian_floor_is_below_the_true_tail() {
+	for n in [401, 625, 1000, 2500] {
+	    let a = 1.0 / (n as f64).sqrt();
+	    for i in -30..=30 {
+		let t = i as f64 / 10.0;
+		let tail = equal_coefficients_tail(n, t);
+		assert!(get_gaussian(a, t) <= tail,
+			"The Gaussian floor {} exceeds Pr[X > {}] = {} for n = {}",
+			get_gaussian(a, t), t, tail, n);
+	    }
+	}
+    }
+
+    #[test]
+    fn gaussian_floor_sharpens_small_coefficients() {
+	let bounder = small_bounder();
+	for a in [0.01, 0.03, 0.049] {
+	    let sharpened = (-20..=20).map(|i| i as f64 / 10.0).filter(|x| {
+		let explanation = bounder.explain(a, *x);
+		let others = explanation.table_value.max(explanation.chernoff_value)
+		    .max(explanation.bernstein_value.unwrap_or(0.0));
+		assert!(bounder.get(a, *x) >= others);
+		explanation.source == BoundSource::Gaussian && explanation.value > others
+	    }).count();
+	    assert!(sharpened > 0, "The Gaussian floor never sharpens D({}, x)", a);
+	}
+	for x in [-1.0, 0.0, 1.0] {
+	    assert_eq!(bounder.explain(GAUSSIAN_FLOOR_MAX_A, x).gaussian_value, None);
+	}
+    }
+
+    #[test]
+    fn gaussian_relax_is_never_weaker() {
+	let plain = small_bounder();
+	let mut gaussian = Bounder::fill_base_sized(DEFAULT_Q, 50, 50);
+	gaussian.relax(D_ITERATIONS, None, true, |_bounder, _iteration| ());
+	let (plain_entries, gaussian_entries) = (entries(plain), entries(&gaussian));
+	for (plain_row, gaussian_row) in plain_entries.iter().zip(&gaussian_entries) {
+	    for (plain_entry, gaussian_entry) in plain_row.iter().zip(gaussian_row) {
+		assert!(gaussian_entry >= plain_entry);
+	    }
+	}
+	assert_ne!(plain_entries, gaussian_entries);
+	for a in [0.0, 0.01, 0.02, 0.05, 0.1, 0.3, 0.7, 1.0] {
+	    for i in -40..=40 {
+		let x = i as f64 / 10.0;
+		assert!(gaussian.get(a, x) >= plain.get(a, x), "D({}, {}) is weaker", a, x);
+	    }
+	}
+    }
+
+    #[test]
+    fn biased_signs_escape_the_rademacher_bounds() {
+	// For n signs with coefficient 1/sqrt(n) and P(e = +1) = p, the modulus of
+	// f_X(v) is (1 - 4p(1-p) sin^2(v/sqrt(n)))^(n/2), which is the Rademacher
+	// |cos(v/sqrt(n))|^n at p = 1/2.
+	let modulus = |n: usize, p: f64, v: f64| {
+	    let a = 1.0 / (n as f64).sqrt();
+	    (1.0 - 4.0 * p * (1.0 - p) * (a * v).sin().powi(2)).powf(n as f64 / 2.0)
+	};
+	let n = 100;
+	let a = 1.0 / (n as f64).sqrt();
+	for i in 1..=20 {
+	    let v = i as f64 / 4.0;
+	    assert!(modulus(n, 0.5, v) <= fx_bound(v, a) + 1e-12, "v = {}", v);
+	}
+	// Even a small bias makes f_X larger than fx_bound allows, so the bounds
+	// cannot simply be reused for biased signs.
+	assert!(modulus(n, 0.6, 1.0) > fx_bound(1.0, a));
+    }
+
+    #[test]
+    fn below_is_empty_for_tiny_p_and_a_positive_tail_for_large_p() {
+	let bounder = small_bounder();
+	for a in [0.1, 0.5] {
+	    assert_eq!(bounder.below(a, 0.0), Vec::<usize>::new());
+	    // Up to x = 1 the table certifies some positive probability for these a,
+	    // though this coarse table gives 0 near the top of the grid.
+	    let tiny = bounder.below(a, 1e-6);
+	    assert!(tiny.iter().all(|y| bounder.threshold_of(*y) > 1.0), "a = {}: {:?}", a, tiny);
+	    // As D(a, x) is non-increasing in x, the cutoffs below a large p are all
+	    // those from some point to the top of the grid.
+	    let large = bounder.below(a, 0.9);
+	    let first = large[0];
+	    assert_eq!(large, (first..2 * bounder.max_bound).collect::<Vec<_>>());
+	    assert!(bounder.threshold_of(first) < 0.0);
+	}
+    }
+
+    #[test]
+    fn columns_match_the_entries() {
+	let check = |bounder: &Bounder| {
+	    for (a, row) in entries(bounder).iter().enumerate() {
+		for (y, entry) in row.iter().enumerate() {
+		    assert_eq!(bounder.column(y)[a], *entry, "column {} row {}", y, a);
+		}
+	    }
+	};
+	let mut bounder = Bounder::fill_base_sized(DEFAULT_Q, 20, 10);
+	check(&bounder);
+	// Changing the table discards the transpose taken before.
+	bounder.relax(D_ITERATIONS, None, false, |_bounder, _iteration| ());
+	check(&bounder);
+	bounder.store_as_single();
+	check(&bounder);
+    }
+
+    #[test]
+    fn disabling_bernstein_only_lowers_the_bound() {
+	let bounder = small_bounder();
+	for a in [0.02, 0.1, 0.3, 0.6, 1.0] {
+	    for i in 5..=40 {
+		let x = -(i as f64) / 10.0;
+		let with = bounder.get(a, x);
+		let without = bounder.get_with_bernstein(a, x, false);
+		assert!(without <= with, "D({}, {}) rose from {} to {}", a, x, with, without);
+		assert!(bounder.get_with_method(BoundMethod::Full, false, a, x, 0.5, 0.8)
+			<= bounder.get_with_var(a, x, 0.5, 0.8));
+	    }
+	}
+	// Below the bottom of the table only the closed-form bounds are of any use.
+	assert!(bounder.get_with_bernstein(0.3, -3.5, false) < bounder.get(0.3, -3.5));
+	assert!(bounder.get_with_method(BoundMethod::Full, false, 0.3, -3.5, 0.5, 0.8)
+		< bounder.get_with_var(0.3, -3.5, 0.5, 0.8));
+    }
+
+    #[test]
+    fn threshold_of_and_y_of_are_inverses() {
+	for thresh_granularity in [7, 50, 300, 1000] {
+	    let max_bound = THRESHOLD_RANGE * thresh_granularity;
+	    // The entry is its own index, so that get shows which index it used.
+	    let bounds = vec![(0..2 * max_bound).map(|y| y as f64).collect(); 2];
+	    let bounder = Bounder::new_manual(Entries::Double(bounds), 2, thresh_granularity,
+					      max_bound);
+	    for y in 0..2 * max_bound {
+		let threshold = bounder.threshold_of(y);
+		assert_eq!(bounder.y_of(threshold), y, "y = {} over {}", y, thresh_granularity);
+		assert_eq!(bounder.explain(1.0, threshold).table_value, y as f64);
+		// Just above the threshold, the next index is used.
+		if y > 0 {
+		    let between = (bounder.threshold_of(y - 1) + threshold) / 2.0;
+		    assert_eq!(bounder.y_of(between), y);
+		    assert!(bounder.threshold_of(y - 1) < between && between <= threshold);
+		}
+	    }
+	}
+    }
+
+    #[test]
+    fn weaker_reference_gives_a_positive_defect() {
+	let bounder = small_bounder();
+	let weaken = |change: &dyn Fn(usize, usize, f64) -> f64| {
+	    let bounds = entries(bounder).iter().enumerate()
+		.map(|(a, row)| row.iter().enumerate().map(|(y, x)| change(a, y, *x)).collect())
+		.collect();
+	    Bounder::new_manual(Entries::Double(bounds), 50, 50, THRESHOLD_RANGE * 50)
+	};
+	let weaker = weaken(&|_a, _y, x| x * 0.9);
+	let defect = bounder.defect(&weaker).unwrap();
+	assert!(defect.gains > 0.0);
+	assert_eq!((defect.regressed, defect.losses, defect.worst), (0, 0.0, None));
+	assert_eq!(defect.improved + defect.unchanged, 50 * 2 * THRESHOLD_RANGE * 50);
+	// Against itself nothing changes, and the other way round it is all loss.
+	assert_eq!(bounder.defect(bounder).unwrap().unchanged, defect.improved + defect.unchanged);
+	let reversed = weaker.defect(bounder).unwrap();
+	assert_eq!((reversed.improved, reversed.losses), (0, defect.gains));
+	// A single stronger entry of the reference is the worst regression.
+	let stronger = weaken(&|a, y, x| if (a, y) == (10, 100) { x + 0.1 } else { x * 0.9 });
+	let defect = bounder.defect(&stronger).unwrap();
+	assert_eq!(defect.regressed, 1);
+	assert_eq!(defect.worst.map(|(a, y, _difference)| (a, y)), Some((10, 100)));
+	assert!(bounder.defect(&Bounder::fill_base_sized(DEFAULT_Q, 20, 10)).is_none());
+    }
+
+    #[test]
+    fn negative_half_beats_symmetry() {
+	// Symmetry would only give Pr[X > -t] >= 1/2, which is why the table has to
+	// store the negative thresholds itself.
+	let bounder = small_bounder();
+	let mut beats_half = false;
+	for a in [0.1, 0.3, 0.5, 0.9] {
+	    for t in [0.1, 0.5, 1.0, 2.0] {
+		assert!(bounder.get(a, -t) >= 0.5);
+		beats_half |= bounder.get(a, -t) > 0.5;
+	    }
+	}
+	assert!(beats_half);
+    }
+
+    #[test]
+    fn values_are_non_increasing_in_the_cutoff() {
+	let bounder = small_bounder();
+	let (min_cutoff, max_cutoff) = bounder.threshold_range();
+	for a in [0.01, 0.2, 0.5, 1.0] {
+	    let steps = ((max_cutoff - min_cutoff) / 0.01) as usize;
+	    let values = (0..=steps).map(|i| bounder.explain(a, min_cutoff + i as f64 * 0.01).value)
+		.collect::<Vec<f64>>();
+	    for (i, pair) in values.windows(2).enumerate() {
+		assert!(pair[1] <= pair[0], "D({}, x) increases after x = {}", a,
+			min_cutoff + i as f64 * 0.01);
+	    }
+	}
+    }
+
+    #[test]
+    fn monotonicity_violations_are_found() {
+	let bounds = vec![vec![0.9, 0.8, 0.85, 0.1],
+			  vec![0.9, 0.7, 0.6, 0.2],
+			  vec![0.8, 0.7, 0.6, 0.0]];
+	let bounder = Bounder::new_manual(Entries::Double(bounds), 3, 1, 2);
+	let (in_cutoff, in_a) = bounder.monotonicity_violations();
+	let positions = |violations: &Violations| violations.iter().map(|(a, y, _)| (*a, *y))
+	    .collect::<Vec<_>>();
+	assert_eq!(positions(&in_cutoff), vec![(0, 1)]);
+	assert_eq!(positions(&in_a), vec![(0, 3)]);
+	assert!((in_cutoff[0].2 - 0.05).abs() < 1e-12 && (in_a[0].2 - 0.1).abs() < 1e-12);
+	assert_eq!(small_bounder().monotonicity_violations(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn explained_source_matches_in_the_bernstein_regime() {
+	let bounder = small_bounder();
+	for a in [0.01, 0.2, 0.6, 1.0] {
+	    for cutoff in [-3.2, -4.0, -6.0] {
+		let explanation = bounder.explain(a, cutoff);
+		let bernstein = explanation.bernstein_value.unwrap();
+		let candidates = [(explanation.table_value, BoundSource::Table),
+				  (bernstein, BoundSource::Bernstein),
+				  (explanation.chernoff_value, BoundSource::Chernoff),
+				  (explanation.gaussian_value.unwrap_or(0.0), BoundSource::Gaussian)];
+		let best = candidates.iter().map(|(value, _source)| *value).fold(0.0, f64::max);
+		assert_eq!(explanation.value, best);
+		assert!(candidates.iter().any(|(value, source)| {
+		    *value == best && *source == explanation.source
+		}), "D({}, {}) is attributed to {:?}", a, cutoff, explanation.source);
+	    }
+	}
+    }
+
+    #[test]
+    fn chernoff_beats_bernstein() {
+	for a in [0.01, 0.1, 0.5, 1.0] {
+	    for t in [-0.5, -1.0, -3.0, -3.5, -5.0] {
+		assert!(get_chernoff(t) >= get_bernstein(a, t), "a = {}, t = {}", a, t);
+	    }
+	}
+    }
+
+    #[test]
+    fn chernoff_holds_for_particular_sequences() {
+	// The worst case behind get_chernoff is that sum log cosh(s a_i) <= s^2 / 2,
+	// so the bound from the exact moment generating function of any particular
+	// sequence is at least as strong.
+	let sequences = [vec![1.0], vec![0.6, 0.8], vec![0.5; 4], vec![0.7, 0.5, 0.4, 0.3, 0.1]];
+	for coefs in sequences.iter() {
+	    let norm = coefs.iter().map(|a: &f64| a * a).sum::<f64>().sqrt();
+	    for s in (1..100).map(|i| i as f64 * 0.1) {
+		let log_mgf = coefs.iter().map(|a| (s * a / norm).cosh().ln()).sum::<f64>();
+		assert!(log_mgf <= s * s / 2.0 + 1e-12, "{:?} at s = {}", coefs, s);
+	    }
+	}
+    }
+
+    #[test]
+    fn zero_gaussian_variance_changes_nothing() {
+	for (a_num, x_num) in [(2, -3), (5, 1), (8, 4), (10, 0)] {
+	    let (a, x) = (a_num as f64 / 10.0, x_num as f64 / 10.0);
+	    assert_eq!(prawitz_bound_gaussian(a, x, 0.5, 0.0),
+		       prawitz_bound(a_num, 10, x_num, 10, DEFAULT_Q.0, DEFAULT_Q.1));
+	}
+	// Whereas a Gaussian part does change the bound.
+	assert!(prawitz_bound_gaussian(0.5, 0.0, 0.5, 0.2) != prawitz_bound_gaussian(0.5, 0.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn largest_coefficient_one_with_tiny_variance() {
+	let bounder = small_bounder();
+	for cutoff in [-0.5, -1e-6, 0.0, 1e-6, 0.5] {
+	    for (min_var, max_var) in [(1e-12, 1e-10), (0.0, 1e-10), (1e-10, 1e-10)] {
+		let value = bounder.get_with_var(1.0, cutoff, min_var, max_var);
+		assert!((0.0..=1.0).contains(&value), "D = {} at cutoff {}", value, cutoff);
+	    }
+	}
+	// The last row of the table is computed with a = 1, rounded up a little.
+	for x_num in [-5, 0, 5] {
+	    let value = prawitz_bound(N as i32 + 1, N, x_num, 10, DEFAULT_Q.0, DEFAULT_Q.1);
+	    assert!((0.0..=1.0).contains(&value));
+	}
+    }
+
+    #[test]
+    fn exact_small_coefficient_is_at_least_the_inflated_one() {
+	for x in [-0.5, 0.5, 1.0] {
+	    let inflated = prawitz_bound_gaussian(0.05, x, 0.5, 0.0);
+	    let exact = prawitz_bound_exact(0.05, x, 0.5, 0.0);
+	    assert_eq!(inflated, prawitz_bound_exact(0.1, x, 0.5, 0.0));
+	    assert!(exact >= inflated, "at x = {}: exact {} < inflated {}", x, exact, inflated);
+	    assert!(exact <= gaussian_tail(x) + DEFAULT_EPSILON);
+	}
+    }
+
+    #[test]
+    fn warm_start_reaches_the_same_fixed_point() {
+	let mut cold = Bounder::fill_base_sized(DEFAULT_Q, 20, 10);
+	cold.relax(D_ITERATIONS, None, false, |_bounder, _iteration| ());
+	let mut partial = Bounder::fill_base_sized(DEFAULT_Q, 20, 10);
+	partial.relax(10, None, false, |_bounder, _iteration| ());
+	let mut warm = Bounder::fill_base_sized(DEFAULT_Q, 20, 10);
+	warm.warm_start(&partial);
+	warm.relax(D_ITERATIONS, None, false, |_bounder, _iteration| ());
+	assert_eq!(entries(&warm), entries(&cold));
+	// And this is indeed a fixed point.
+	let fixed = entries(&cold);
+	cold.relax(1, None, false, |_bounder, _iteration| ());
+	assert_eq!(entries(&cold), fixed);
+    }
+
+    #[test]
+    fn single_precision_only_loses_what_rounding_reports() {
+	let small = small_bounder();
+	let double = Bounder::new_manual(Entries::Double(entries(small)), small.coef_granularity,
+					 small.thresh_granularity, small.max_bound);
+	let mut single = Bounder::new_manual(Entries::Double(entries(small)), small.coef_granularity,
+					     small.thresh_granularity, small.max_bound);
+	let max_loss = single.store_as_single();
+	assert!(max_loss > 0.0 && max_loss < 1e-7, "max loss {}", max_loss);
+	let (bottom, top) = double.threshold_range();
+	let mut max_discrepancy: f64 = 0.0;
+	for i in 0..=100 {
+	    let a = i as f64 / 100.0;
+	    for j in 0..200 {
+		let cutoff = bottom + (top - bottom) * j as f64 / 200.0;
+		let (exact, rounded) = (double.get(a, cutoff), single.get(a, cutoff));
+		assert!(rounded <= exact, "f32 table is above the f64 one at D({}, {})", a, cutoff);
+		max_discrepancy = max_discrepancy.max(exact - rounded);
+	    }
+	}
+	assert!(max_discrepancy <= max_loss, "max discrepancy {:e} exceeds the max loss {:e}",
+		max_discrepancy, max_loss);
+    }
+
+    #[test]
+    fn unachievable_integration_error_falls_back_to_trivial_bound() {
+	// Here the error in evaluating the integrands, 2^-40 (2 + tx), is already
+	// more than epsilon over the interval, which used to trip the assertion.
+	let (a, x) = (0.5, 1e9);
+	assert_eq!(compute_f(a, x, PI / a, 0.5, DEFAULT_EPSILON, 0.0), 0.0);
+	assert_eq!(lipschitz_integrate(&|u| u, 0.0, 1.0, 1e-3, 1.0, 1e-3), None);
+	// Just below that, the integral is still computed to within epsilon.
+	let integral = lipschitz_integrate(&|u| u * u, 0.0, 1.0, 1e-3, 2.0, 0.9e-3).unwrap();
+	assert!((integral - 1.0 / 3.0).abs() < 1e-3, "integral {}", integral);
+    }
+
+    #[test]
+    fn beyond_the_top_of_the_table_is_zero() {
+	// A table covering only cutoffs in [-1, 1).
+	let bounder = Bounder::new_manual(Entries::Double(vec![vec![0.4; 20]; 10]), 10, 10, 10);
+	let (_bottom, top) = bounder.threshold_range();
+	assert!(top < 1.0);
+	let explanation = bounder.explain(0.5, 1.5);
+	assert_eq!(explanation.source, BoundSource::BeyondTable);
+	assert_eq!(explanation.value, 0.0);
+	assert!(!bounder.warned_beyond_table.load(Ordering::Relaxed));
+	assert_eq!(bounder.get(0.5, 1.5), 0.0);
+	assert!(bounder.warned_beyond_table.load(Ordering::Relaxed));
+	assert!(bounder.get(0.5, top - 0.2) > 0.0);
+	// Near the top is judged by this table's range, not THRESHOLD_RANGE.
+	assert!(bounder.is_near_top(0.8));
+	assert!(!bounder.is_near_top(0.2));
+	assert!(!small_bounder().is_near_top(0.8));
+	assert!(small_bounder().is_near_top(2.8));
+    }
+
+    #[test]
+    fn update_cell_reproduces_the_old_fixed_point() {
+	// The table which the old Bounder::new built is a fixed point, so each entry
+	// is the larger of precomputation #1 and the update from the rest of it.
+	let reference = reference_new(DEFAULT_Q, 20, 10);
+	let base = entries(&Bounder::fill_base_sized(DEFAULT_Q, 20, 10));
+	let mut improved = 0;
+	for a in 0..20 {
+	    for y in 0..60 {
+		let update = Bounder::update_cell(&reference, 20, 10, 30, a, y, false);
+		assert_eq!(reference[a][y], base[a][y].max(update.value), "at ({}, {})", a, y);
+		improved += (update.value > base[a][y]) as usize;
+	    }
+	}
+	assert!(improved > 0);
+    }
+
+    #[test]
+    fn sandwich_lower_is_at_most_upper() {
+	let bounder = small_bounder();
+	let (bottom, top) = bounder.threshold_range();
+	for i in 1..=20 {
+	    let a = i as f64 / 20.0;
+	    for j in 0..=100 {
+		let cutoff = bottom + (top - bottom) * j as f64 / 100.0;
+		let (lower, upper) = (bounder.get(a, cutoff), bounder.get_upper(a, cutoff));
+		assert!(lower <= upper, "{} > {} at D({}, {})", lower, upper, a, cutoff);
+	    }
+	}
+    }
+
+    #[test]
+    fn rounding_up_costs_tightness() {
+	// Here a_1 <= 0.501 and the threshold 0.501 are snapped to 0.5045.
+	let (snapped, exact) = round_sensitivity(1001, THRESHOLD_RANGE * N + 1001, DEFAULT_Q);
+	assert!(exact > snapped, "exact {} <= snapped {}", exact, snapped);
+	// On the grid of round_up nothing changes.
+	let (snapped, exact) = round_sensitivity(1008, THRESHOLD_RANGE * N + 1000, DEFAULT_Q);
+	assert_eq!(snapped, exact);
+    }
+}
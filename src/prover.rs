@@ -1,13 +1,21 @@
-use std::io::{self, Write};
+use std::{cell::Cell, fmt, io::{self, Write}, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::{Duration, Instant}};
 
-use crate::prawitz::Bounder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::prawitz::{BoundMethod, Bounder};
 use crate::restriction::*;
 use crate::case::*;
 use crate::extrema::*;
+use crate::util::format_float;
 
-// To mitigate risk of floating-point errors.
-const EPSILON: f64 = 0.0000000001;
 const DELTA_ERROR: f64 = 0.000001;
+// When profiling, only one in this many calls to can_be_resolved is timed.
+const PROFILE_SAMPLE_EVERY: u128 = 64;
+
+thread_local! {
+    static THREAD_RESOLVE_CALLS: Cell<u128> = const { Cell::new(0) };
+}
 
 /**
  * Represents a sequence of intervals. Interval i is
@@ -18,6 +26,19 @@ pub struct Seq {
     pub denominator: u128,
 }
 
+/**
+ * The sum of the squares of the given numerators. When reading a case we check
+ * with square_sums_fit that this cannot overflow for any Seq in the search, but
+ * we check again here, as an overflow would otherwise wrap around to a tiny
+ * variance in release builds.
+ */
+fn square_sum(numerators: impl Iterator<Item = u128>) -> u128 {
+    numerators.fold(0u128, |sum, x| {
+	x.checked_mul(x).and_then(|square| sum.checked_add(square))
+	    .expect("Overflow in a sum of squares of numerators!")
+    })
+}
+
 impl Seq {
     pub fn new(numerator: u128, denominator: u128, max_depth: usize) -> Seq {
         Seq {
@@ -26,6 +47,20 @@ impl Seq {
         }
     }
 
+    /**
+     * The Seq of the intervals containing the given coefficients, with one entry
+     * for each of them. A coefficient on the boundary of two intervals is put in
+     * the upper one, except that 1 is put in the last interval [(d-1)/d, 1].
+     */
+    pub fn from_coefficients(coefs: &[f64], denominator: u128) -> Seq {
+	Seq {
+	    numerators: coefs.iter()
+		.map(|a| scale_to_numerator(*a, denominator).min(denominator - 1))
+		.collect(),
+	    denominator
+	}
+    }
+
     pub fn set(&mut self, index: usize, numerator: u128) {
         self.numerators[index] = numerator;
     }
@@ -42,17 +77,41 @@ impl Seq {
         (self.numerators[index] + 1) as f64 / self.denominator as f64
     }
 
+    /**
+     * The interval [get_min(index), get_max(index)] in which a_index lies.
+     */
+    pub fn get_interval(&self, index: usize) -> Interval {
+	Interval { lb: self.get_min(index), ub: self.get_max(index) }
+    }
+
     pub fn iter_numerators(&self) -> impl Iterator<Item = &u128> {
         self.numerators.iter()
     }
 
+    /**
+     * Computes the minimum possible variance of the first depth terms (i.e. each
+     * a_i at the bottom of its interval)
+     */
+    pub fn min_partial_variance(&self, depth: usize) -> f64 {
+	let num_sum = square_sum(self.numerators.iter().take(depth).copied());
+	num_sum as f64 / ((self.denominator * self.denominator) as f64)
+    }
+
+    /**
+     * Computes the maximum possible variance of the first depth terms (i.e. each
+     * a_i at the top of its interval)
+     */
+    pub fn max_partial_variance(&self, depth: usize) -> f64 {
+	let num_sum = square_sum(self.numerators.iter().take(depth).map(|x| *x + 1));
+	num_sum as f64 / ((self.denominator * self.denominator) as f64)
+    }
+
     /**
      * Computes the minimum possible variance (i.e. each a_i at the bottom
      * of its interval)
      */
     pub fn min_variance(&self) -> f64 {
-        let num_sum: u128 = self.numerators.iter().map(|x| *x * *x).sum();
-        num_sum as f64 / ((self.denominator * self.denominator) as f64)
+	self.min_partial_variance(self.numerators.len())
     }
 
     /**
@@ -60,8 +119,20 @@ impl Seq {
      * of its interval)
      */
     pub fn max_variance(&self) -> f64 {
-        let num_sum: u128 = self.numerators.iter().map(|x| (*x + 1) * (*x + 1)).sum();
-        num_sum as f64 / ((self.denominator * self.denominator) as f64)
+	self.max_partial_variance(self.numerators.len())
+    }
+
+    /**
+     * Returns the largest numerator a_depth can take while keeping the minimum
+     * possible variance at most 1, given the numerators before depth and that the
+     * numerators after depth contribute at least remaining to the sum of squares.
+     * Returns None if even a zero numerator would exceed the variance budget.
+     */
+    pub fn max_numerator_in_variance(&self, depth: usize, remaining: u128) -> Option<u128> {
+	let prefix = square_sum(self.numerators.iter().take(depth).copied());
+	(self.denominator * self.denominator)
+	    .checked_sub(prefix + remaining)
+	    .map(|budget| budget.isqrt())
     }
 
     /**
@@ -69,17 +140,25 @@ impl Seq {
      * In cases where a Seq may or may not satisfy a restriction (i.e. cutoff between min and max)
      * we return true; we return whether it is possible that the Seq satisfies the restrictions.
      */
-    pub fn could_satisfy_restrictions(&self, hints: &Vec<Restriction>,
+    pub fn could_satisfy_restrictions(&self, hints: &[Restriction],
 				  depth: usize) -> bool {
+	self.failed_restriction(hints, depth).is_none()
+    }
+
+    /**
+     * The index of the first of the given Restrictions which this Seq cannot
+     * satisfy, if any, so that the search can count which restriction pruned it.
+     */
+    pub fn failed_restriction(&self, hints: &[Restriction], depth: usize) -> Option<usize> {
         use Restriction::*;
-        let mut satisfies_all = true;
-        'test_hints: for hint in hints.iter() {
+	let mut failed = None;
+	'test_hints: for (hint_index, hint) in hints.iter().enumerate() {
             match *hint {
                 InitialSumUpperBound(sum_depth, bound) => {
                     let sum: u128 = self.numerators.iter()
 			.take(depth.min(sum_depth)).sum();
                     if (sum as f64) / (self.denominator as f64) > bound {
-                        satisfies_all = false;
+			failed = Some(hint_index);
                         break 'test_hints;
                     }
                 }
@@ -90,29 +169,177 @@ impl Seq {
                         // Here we add sum_depth (= +1 per numerator) to get an upper bound
                         if (sum as f64 + sum_depth as f64) / (self.denominator as f64)
 			    < bound {
-                            satisfies_all = false;
+			    failed = Some(hint_index);
                             break 'test_hints;
                         }
                     }
                 }
+		InitialSumUpperBoundNum(sum_depth, bound) => {
+		    let sum: u128 = self.numerators.iter().take(depth.min(sum_depth)).sum();
+		    if sum > bound {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		InitialSumLowerBoundNum(sum_depth, bound) => {
+		    // As in InitialSumLowerBound, each a_i is at most (numerator + 1) / d.
+		    if depth >= sum_depth {
+			let sum: u128 = self.numerators.iter().take(sum_depth).sum();
+			if sum + (sum_depth as u128) < bound {
+			    failed = Some(hint_index);
+			    break 'test_hints;
+			}
+		    }
+		}
                 MidSumUpperBound(start, end, bound) => {
                     let sum: u128 = self.numerators.iter()
 			.take(depth.min(end)).skip(start).sum();
                     if (sum as f64) / (self.denominator as f64) > bound {
-                        satisfies_all = false;
+			failed = Some(hint_index);
                         break 'test_hints;
                     }
                 }
                 Bounds(index, interval) => {
-                    if index < depth &&	(self.get_max(index) < interval.lb
-			 || self.get_min(index) > interval.ub) {
-                        satisfies_all = false;
+		    if index < depth && self.get_interval(index).intersect(&interval).is_empty() {
+			failed = Some(hint_index);
                         break 'test_hints;
                     }
                 }
+		RangeBounds(start, end, interval) => {
+		    if (start..end.min(depth))
+			.any(|index| self.get_interval(index).intersect(&interval).is_empty()) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		Equals(index, numerator) => {
+		    if index < depth && self.get_min_numerator(index) != numerator {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		ExcludeNumerator(index, numerator) => {
+		    if index < depth && self.get_min_numerator(index) == numerator {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		EqualPrefix(prefix_len) => {
+		    // The intervals are closed, so any common value of the first
+		    // prefix_len coefficients lies in a single common interval.
+		    let first = self.get_min_numerator(0);
+		    if (1..depth.min(prefix_len))
+			.any(|index| self.get_min_numerator(index) != first) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		DistinctValuesUpperBound(max_distinct) => {
+		    // A sequence with at most max_distinct distinct values is covered by
+		    // some choice of intervals where equal values share a numerator, so
+		    // we count distinct numerators. Those set so far are fixed, so we
+		    // need not wait for the full depth. As they are non-increasing, the
+		    // distinct ones are those which differ from their predecessor.
+		    let distinct = (0..depth).filter(|i| {
+			*i == 0 || self.get_min_numerator(*i) != self.get_min_numerator(i - 1)
+		    }).count();
+		    if distinct > max_distinct {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		ZeroAfter(start) => {
+		    if (start..depth).any(|index| self.get_min_numerator(index) != 0) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		MaxCoefLowerBound(bound) => {
+		    // The a_i are non-increasing, so this is just a_0, but we don't
+		    // rely on that here.
+		    if depth >= 1
+			&& (0..depth).all(|index| self.get_max(index) < bound) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		TailMaxUpperBound(start, bound) => {
+		    // As with MaxCoefLowerBound, we don't rely on the a_i being
+		    // non-increasing, although then only a_start matters.
+		    if (start..depth).any(|index| self.get_min(index) > bound) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		TotalSumUpperBound(bound) => {
+		    // Every a_i is non-negative, so the smallest possible sum of those
+		    // set so far is already a lower bound on the whole sum.
+		    let sum: u128 = self.numerators.iter().take(depth).sum();
+		    if (sum as f64) / (self.denominator as f64) > bound {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		CloseCoefficients(i, j, delta) => {
+		    // Violated only if the two intervals are further apart than delta.
+		    if i.max(j) < depth
+			&& (self.get_min(i) - self.get_max(j) > delta
+			    || self.get_min(j) - self.get_max(i) > delta) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		PartialVarianceBounds(var_depth, interval) => {
+		    if depth >= var_depth
+			&& (self.max_partial_variance(var_depth) < interval.lb
+			    || self.min_partial_variance(var_depth) > interval.ub) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		GeometricDecay(start, ratio) => {
+		    // Violated only if a_{i+1} is above ratio * a_i wherever each lies
+		    // in its interval.
+		    if (start..depth.saturating_sub(1))
+			.any(|index| self.get_min(index + 1) > ratio * self.get_max(index)) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		LinearBounds(ref terms, lb, ub) => {
+		    // Each term is smallest at the bottom of its interval if its weight
+		    // is positive, and at the top otherwise, and vice versa.
+		    if terms.iter().all(|(index, _)| *index < depth) {
+			let (min, max) = terms.iter().fold((0.0, 0.0), |(min, max), (index, weight)| {
+			    let (low, high) = (weight * self.get_min(*index),
+					       weight * self.get_max(*index));
+			    (min + low.min(high), max + low.max(high))
+			});
+			if max < lb || min > ub {
+			    failed = Some(hint_index);
+			    break 'test_hints;
+			}
+		    }
+		}
+		Between(from, to, ref inner) => {
+		    if from <= depth && depth <= to
+			&& !self.could_satisfy_restrictions(std::slice::from_ref(inner.as_ref()), depth) {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
+		PrefixVarianceFractionLowerBound(var_depth, frac) => {
+		    // The total variance sum a_i^2 is exactly 1, so the fraction of it
+		    // which the prefix carries is just the prefix variance. To only prune
+		    // what cannot possibly reach frac, we use the largest prefix variance.
+		    if depth >= var_depth && self.max_partial_variance(var_depth) < frac {
+			failed = Some(hint_index);
+			break 'test_hints;
+		    }
+		}
             }
         }
-        satisfies_all
+	failed
     }
 
     /**
@@ -122,48 +349,280 @@ impl Seq {
      *
      * Note that even if min_remaining_var < 0, this still works as expected due to
      * some case analysis in bounder.get_with_var(...).
+     *
+     * Here depth is the number of a_i which have been set. The search always sets
+     * a_0 first, but depth = 0 is allowed, in which case nothing is known about
+     * the largest coefficient and we must take it to be 1.
      */
     pub fn can_be_resolved(&self, bounder: &Bounder, case: &Case,
 			   depth: usize) -> bool {
-        let min_remaining_var = 1.0 - self.max_variance();
-        let max_remaining_var = 1.0 - self.min_variance();
         if self.min_variance() > 1.0 {
             // The variance is too large and so we can ignore this case.
             true
         } else {
-            let mut total = 0.0;
-            for signs_code in 0..(1 << depth) {
-                let mut threshold_adjustment_numerator: i128 = 0;
-                let mut sta = signs_code;
-                for numerator in self.numerators.iter().take(depth) {
-                    if sta % 2 == 1 {
-                        threshold_adjustment_numerator += (*numerator + 1) as i128;
-                    } else {
-                        threshold_adjustment_numerator -= (*numerator) as i128;
-                    }
-                    sta /= 2;
-                }
-                let new_threshold = case.threshold +
-		    (threshold_adjustment_numerator as f64 / self.denominator as f64);
-                // In this case we care about P[ X >= new_bound ]
-                total += bounder.get_with_var(self.get_max(depth - 1), new_threshold,
-					      min_remaining_var, max_remaining_var);
-            }
-            let prob_lower_bound = total / (1 << depth) as f64;
+	    let prob_lower_bound = self.box_lower_bound(bounder, case.threshold, case.method,
+							case.use_bernstein, depth);
+	    // For a positive threshold, symmetry gives P[ X >= threshold ] <= 1/2,
+	    // so anything larger means the Bounder is returning impossible values.
+	    debug_assert!(case.threshold <= 0.0 || prob_lower_bound <= 0.5 + RESOLVE_EPSILON,
+			  "Bounder gave an impossible lower bound of {} at threshold {}",
+			  prob_lower_bound, case.threshold);
 
             // This case can be resolved if our probability is above the cutoff.
-            prob_lower_bound >= case.prob_cutoff + EPSILON
+	    prob_lower_bound >= case.prob_cutoff + case.resolve_epsilon
         }
     }
 
-    pub fn _print_compact(&self) {
+    /**
+     * Our lower bound on P[ X >= threshold ] over every sequence whose first depth
+     * a_i lie in the intervals of this Seq, averaging the bound from the Bounder
+     * over every choice of signs of those a_i. This is what can_be_resolved
+     * compares with prob_cutoff, where method and use_bernstein are as in Case.
+     */
+    pub fn box_lower_bound(&self, bounder: &Bounder, threshold: f64, method: BoundMethod,
+			   use_bernstein: bool, depth: usize) -> f64 {
+	let min_remaining_var = 1.0 - self.max_variance();
+	let max_remaining_var = 1.0 - self.min_variance();
+	// The remaining a_i are at most the last one set, as they are non-increasing.
+	let max_remaining_coef = if depth == 0 { 1.0 } else { self.get_max(depth - 1) };
+	// Each term is a lower bound over the whole box for one choice of signs. If
+	// bit i of signs_code is set then a_i has sign -1, and the rest of X must
+	// reach threshold + a_i, which is hardest at the top of its interval, so we
+	// use numerator + 1. Otherwise the rest must reach threshold - a_i, which is
+	// hardest at the bottom, so we use numerator. Neither choice depends on the
+	// others, and the largest remaining coefficient and the range of remaining
+	// variance are the worst cases over the whole box, so they are sound for
+	// every term even though a particular endpoint may make them loose.
+	let mut total = 0.0;
+	for signs_code in 0..(1 << depth) {
+	    let mut threshold_adjustment_numerator: i128 = 0;
+	    let mut sta = signs_code;
+	    for numerator in self.numerators.iter().take(depth) {
+		if sta % 2 == 1 {
+		    threshold_adjustment_numerator += (*numerator + 1) as i128;
+		} else {
+		    threshold_adjustment_numerator -= (*numerator) as i128;
+		}
+		sta /= 2;
+	    }
+	    let new_threshold = threshold +
+		(threshold_adjustment_numerator as f64 / self.denominator as f64);
+	    // In this case we care about P[ X >= new_bound ]
+	    total += bounder.get_with_method(method, use_bernstein,
+					     max_remaining_coef, new_threshold,
+					     min_remaining_var, max_remaining_var);
+	}
+	total / (1 << depth) as f64
+    }
+
+    /**
+     * A fast approximation to can_be_resolved, which replaces the average over all
+     * 2^depth choices of signs with the single most favourable one, in which every
+     * sign lowers the threshold. This is at least as large as each term of the
+     * average, so this resolves everything that can_be_resolved does and more.
+     * It over-prunes, and so is NOT a valid proof; it is only useful for a quick
+     * scan to see whether a full run has any hope of succeeding.
+     */
+    pub fn can_be_resolved_approx(&self, bounder: &Bounder, case: &Case,
+				  depth: usize) -> bool {
+	if self.min_variance() > 1.0 {
+	    return true;
+	}
+	let min_remaining_var = 1.0 - self.max_variance();
+	let max_remaining_var = 1.0 - self.min_variance();
+	let max_remaining_coef = if depth == 0 { 1.0 } else { self.get_max(depth - 1) };
+	let shift: u128 = self.numerators.iter().take(depth).sum();
+	let new_threshold = case.threshold - (shift as f64 / self.denominator as f64);
+	let prob_lower_bound = bounder.get_with_method(case.method, case.use_bernstein,
+						       max_remaining_coef,
+						       new_threshold, min_remaining_var,
+						       max_remaining_var);
+	prob_lower_bound >= case.prob_cutoff + case.resolve_epsilon
+    }
+
+    pub fn _print_compact(&self, precision: Option<usize>) {
         for numer in self.numerators.iter() {
-            print!("{:.3} ", *numer as f64 / self.denominator as f64);
+	    print!("{:.*} ", precision.unwrap_or(3), *numer as f64 / self.denominator as f64);
         }
         println!();
     }
 }
 
+/**
+ * Counts of what happened at each depth of the search tree, so that we can see
+ * whether branches are being pruned early or only at the leaves.
+ */
+pub struct SearchStats {
+    visited: Vec<u128>,
+    pruned: Vec<u128>,
+    resolved: Vec<u128>,
+    expanded: Vec<u128>,
+    children: Vec<u128>,
+    leaves: Vec<u128>,
+    // How many nodes each of the case's restrictions pruned, counting each node
+    // against the first restriction it fails.
+    pruned_by: Vec<u128>,
+}
+
+impl SearchStats {
+    pub fn new(max_depth: usize, num_restrictions: usize) -> SearchStats {
+	SearchStats {
+	    visited: vec![0; max_depth + 1],
+	    pruned: vec![0; max_depth + 1],
+	    resolved: vec![0; max_depth + 1],
+	    expanded: vec![0; max_depth + 1],
+	    children: vec![0; max_depth + 1],
+	    leaves: vec![0; max_depth + 1],
+	    pruned_by: vec![0; num_restrictions],
+	}
+    }
+
+    fn include_expansion(&mut self, depth: usize, min: u128, max: u128) {
+	self.expanded[depth] += 1;
+	self.children[depth] += (max + 1).saturating_sub(min);
+    }
+
+    fn merge(&mut self, other: SearchStats) {
+	for (counts, other_counts) in [(&mut self.visited, other.visited),
+				       (&mut self.pruned, other.pruned),
+				       (&mut self.resolved, other.resolved),
+				       (&mut self.expanded, other.expanded),
+				       (&mut self.children, other.children),
+				       (&mut self.leaves, other.leaves),
+				       (&mut self.pruned_by, other.pruned_by)] {
+	    for (count, other_count) in counts.iter_mut().zip(other_counts) {
+		*count += other_count;
+	    }
+	}
+    }
+
+    /**
+     * Prints, for each depth, how many nodes were visited and resolved there, and
+     * the average number of children of each node that was expanded.
+     */
+    pub fn print(&self) {
+	println!("depth\tvisited\tresolved\tbranching");
+	for depth in 0..self.visited.len() {
+	    let branching = if self.expanded[depth] > 0 {
+		self.children[depth] as f64 / self.expanded[depth] as f64
+	    } else {
+		0.0
+	    };
+	    println!("{}\t{}\t{}\t{:.2}", depth, self.visited[depth],
+		     self.resolved[depth], branching);
+	}
+    }
+
+    /**
+     * Prints how many nodes each restriction pruned, so that those which do none of
+     * the work can be spotted and removed.
+     */
+    pub fn print_pruning(&self, restrictions: &[Restriction]) {
+	for (restriction, count) in restrictions.iter().zip(&self.pruned_by) {
+	    println!("{}\t{:?}", count, restriction);
+	}
+    }
+
+    /**
+     * A CSV with one row for each depth, of how many nodes were visited there, and
+     * how many of those were pruned by the restrictions, resolved, expanded, or
+     * left as leaves to be included in the results. Any others were not expanded
+     * as every child would have too large a variance.
+     */
+    pub fn to_csv(&self) -> String {
+	let mut csv = String::from("depth,visited,pruned,resolved,expanded,leaves\n");
+	for depth in 0..self.visited.len() {
+	    csv += &format!("{},{},{},{},{},{}\n", depth, self.visited[depth],
+			    self.pruned[depth], self.resolved[depth],
+			    self.expanded[depth], self.leaves[depth]);
+	}
+	csv
+    }
+}
+
+/**
+ * Coarse timing of the search, to see whether time goes on can_be_resolved (and
+ * its loop over the 2^depth signs) or on traversing the tree. Timing every call
+ * would itself cost a noticeable fraction of the call, so we time only a sample
+ * of them and scale up.
+ */
+pub struct Profile {
+    resolve_calls: u128,
+    sampled_calls: u128,
+    sampled_time: Duration,
+    get_with_var_calls: u128,
+    search_time: Duration,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+	Profile {
+	    resolve_calls: 0,
+	    sampled_calls: 0,
+	    sampled_time: Duration::ZERO,
+	    get_with_var_calls: 0,
+	    search_time: Duration::ZERO,
+	}
+    }
+
+    fn merge(&mut self, other: Profile) {
+	self.resolve_calls += other.resolve_calls;
+	self.sampled_calls += other.sampled_calls;
+	self.sampled_time += other.sampled_time;
+	self.get_with_var_calls += other.get_with_var_calls;
+	self.search_time += other.search_time;
+    }
+
+    /**
+     * Calls resolve, which checks whether seq can be resolved at the given depth,
+     * either exactly or approximately.
+     */
+    fn time_resolve(&mut self, seq: &Seq, depth: usize, approx: bool,
+		    resolve: impl Fn() -> bool) -> bool {
+	self.resolve_calls += 1;
+	// can_be_resolved returns before looking anything up if the variance is too large.
+	if seq.min_variance() <= 1.0 {
+	    self.get_with_var_calls += if approx { 1 } else { 1 << depth };
+	}
+	// Each thread's part of the search is split into many small pieces, so we
+	// count calls per thread to decide which to time, rather than per piece.
+	let thread_calls = THREAD_RESOLVE_CALLS.with(|calls| {
+	    calls.set(calls.get() + 1);
+	    calls.get()
+	});
+	if thread_calls.is_multiple_of(PROFILE_SAMPLE_EVERY) {
+	    let start = Instant::now();
+	    let resolved = resolve();
+	    self.sampled_time += start.elapsed();
+	    self.sampled_calls += 1;
+	    resolved
+	} else {
+	    resolve()
+	}
+    }
+
+    /**
+     * Prints how the time of the search, summed over all threads, splits between
+     * can_be_resolved and everything else.
+     */
+    pub fn print(&self) {
+	let resolve_secs = if self.sampled_calls > 0 {
+	    self.sampled_time.as_secs_f64() * self.resolve_calls as f64
+		/ self.sampled_calls as f64
+	} else {
+	    0.0
+	};
+	let total_secs = self.search_time.as_secs_f64();
+	println!("{} calls to can_be_resolved, of which {} were timed.",
+		 self.resolve_calls, self.sampled_calls);
+	println!("Estimated time in can_be_resolved: {:.3}s of {:.3}s ({:.1}%)",
+		 resolve_secs, total_secs, 100.0 * resolve_secs / total_secs.max(f64::MIN_POSITIVE));
+	println!("Estimated time in the rest of the search: {:.3}s",
+		 (total_secs - resolve_secs).max(0.0));
+	println!("{} calls to get_with_var", self.get_with_var_calls);
+    }
+}
+
 /**
  * Recieves a newly-generated seq and then:
  *  - tests that seq satisfies the given hints; if it doesn't, then do nothing.
@@ -173,65 +632,649 @@ impl Seq {
  *  - - go one level deeper into seq
  *  - else:
  *  - - We fail to prove this case. Update the results accordingly.
+ *
+ * On return, the numerators of seq from index depth onwards are all 0 again.
  */
-fn simulate_rec(bounder: &Bounder, seq: &mut Seq, results: &mut Results,
-        case: &Case, depth: usize) {
-    if seq.could_satisfy_restrictions(&case.restrictions, depth)
-	&& !seq.can_be_resolved(bounder, case, depth) {
-        if depth < case.max_depth {
-            let min = case.get_lower_bound(depth);
-            let max = seq.get_min_numerator(depth - 1).min(case.get_upper_bound(depth));
-            for numerator in min..=max {
-                seq.set(depth, numerator);
-                simulate_rec(bounder, seq, results, case, depth + 1);
-            }
-            seq.set(depth, 0);
+fn simulate_rec(bounder: &Bounder, search: &mut Search, case: &Case, depth: usize) {
+    search.stats.visited[depth] += 1;
+    let seq = &search.seq;
+    let failed = seq.failed_restriction(&case.restrictions, depth);
+    if failed.is_none() {
+	let resolve = || if search.approx {
+	    seq.can_be_resolved_approx(bounder, case, depth)
+	} else {
+	    seq.can_be_resolved(bounder, case, depth)
+	};
+	let resolved = match search.profile.as_mut() {
+	    Some(profile) => profile.time_resolve(seq, depth, search.approx, resolve),
+	    None => resolve(),
+	};
+	if resolved {
+	    search.stats.resolved[depth] += 1;
+	} else if depth < case.max_depth {
+	    // Every completion of the prefix with a larger numerator here has minimum
+	    // variance above 1, counting the lower bounds on the later a_i, so we
+	    // don't iterate over those at all. If that rules out every numerator, the
+	    // node is counted as resolved.
+	    let remaining = case.min_square_sum_from(depth + 1);
+	    match seq.max_numerator_in_variance(depth, remaining) {
+		None => search.stats.resolved[depth] += 1,
+		Some(variance_max) => {
+		    let min = case.get_lower_bound(depth);
+		    let max = seq.get_min_numerator(depth - 1).min(case.get_upper_bound(depth))
+			.min(variance_max);
+		    search.stats.include_expansion(depth, min, max);
+		    for numerator in min..=max {
+			search.seq.set(depth, numerator);
+			simulate_rec(bounder, search, case, depth + 1);
+		    }
+		    search.seq.set(depth, 0);
+		}
+	    }
         } else {
-            results.include_seq(seq, depth);
+	    search.stats.leaves[depth] += 1;
+	    search.results.include_seq(seq, depth);
         }
+    } else if let Some(index) = failed {
+	search.stats.pruned[depth] += 1;
+	search.stats.pruned_by[index] += 1;
+    }
+}
+
+/**
+ * The state of one thread's part of the search. Each thread routes the sequences
+ * it finds into the subcases of its own Results, so that there is no contention
+ * between threads, and these are merged once the search is done.
+ */
+struct Search {
+    seq: Seq,
+    results: Results,
+    stats: SearchStats,
+    profile: Option<Profile>,
+    approx: bool,
+}
+
+impl Search {
+    fn new(case: &Case, profile: bool, approx: bool) -> Search {
+	Search {
+	    seq: Seq::new(0, case.denominator, case.max_depth),
+	    results: Results::new(case),
+	    stats: SearchStats::new(case.max_depth, case.restrictions.len()),
+	    profile: profile.then(Profile::new),
+	    approx,
+	}
+    }
+
+    /**
+     * Searches below the first depth numerators of seq, which must already be set.
+     * These are reset to 0 afterwards, so that seq is left as it was made and the
+     * Search can be run again, whether below another value of a_0 or not.
+     */
+    fn run(&mut self, bounder: &Bounder, case: &Case, depth: usize) {
+	let start_time = Instant::now();
+	simulate_rec(bounder, self, case, depth);
+	for index in 0..depth {
+	    self.seq.set(index, 0);
+	}
+	debug_assert!(self.seq.numerators.iter().all(|&numerator| numerator == 0));
+	if let Some(profile) = self.profile.as_mut() {
+	    profile.search_time += start_time.elapsed();
+	}
+    }
+
+    fn merge(mut self, other: Search) -> Search {
+	self.absorb(other);
+	self
+    }
+
+    fn absorb(&mut self, other: Search) {
+	self.results.merge(other.results);
+	self.stats.merge(other.stats);
+	if let (Some(profile), Some(other_profile)) = (self.profile.as_mut(), other.profile) {
+	    profile.merge(other_profile);
+	}
     }
 }
 
+/**
+ * For each possible interval of a_0, how far D(a_0, threshold) is above
+ * prob_cutoff, using the largest value of a_0 in the interval as D is
+ * non-increasing in a. Returns a list of (that value of a_0, slack).
+ */
+fn slacks(bounder: &Bounder, case: &Case) -> Vec<(f64, f64)> {
+    let min = case.get_lower_bound(0);
+    let max = case.get_upper_bound(0);
+    (min..=max).map(|numerator| {
+	let a = (numerator + 1) as f64 / case.denominator as f64;
+	(a, bounder.get(a.min(1.0), case.threshold) - case.prob_cutoff)
+    }).collect()
+}
+
+/**
+ * A cheap check before running a case, printing a summary of the slacks. Where
+ * the slack is negative the bound for a_0 alone is below prob_cutoff. This says
+ * nothing definite about the search, which starts at depth 1 and averages over
+ * the signs of the a_i, but shows where it will have to work hardest.
+ */
+pub fn print_slack(bounder: &Bounder, case: &Case) {
+    let slacks = slacks(bounder, case);
+    if slacks.is_empty() {
+	println!("No possible values of a_0!");
+	return;
+    }
+    let min_slack = slacks.iter().map(|(_a, slack)| *slack).fold(f64::INFINITY, f64::min);
+    let max_slack = slacks.iter().map(|(_a, slack)| *slack).fold(f64::NEG_INFINITY, f64::max);
+    let mean_slack = slacks.iter().map(|(_a, slack)| *slack).sum::<f64>() / slacks.len() as f64;
+    println!("Slack D(a_0, {}) - {} over {} intervals of a_0:", case.threshold,
+	     case.prob_cutoff, slacks.len());
+    println!("min {}, max {}, mean {}", min_slack, max_slack, mean_slack);
+    match slacks.iter().position(|(_a, slack)| *slack < 0.0) {
+	Some(index) => {
+	    let count = slacks.iter().filter(|(_a, slack)| *slack < 0.0).count();
+	    println!("Negative for {} intervals, starting from a_0 <= {}: there D(a_0, {}) is below {}.",
+		     count, slacks[index].0, case.threshold, case.prob_cutoff);
+	}
+	None => println!("Non-negative everywhere: D(a_0, {}) is at least {} for every interval of a_0.",
+			 case.threshold, case.prob_cutoff),
+    }
+}
+
+/**
+ * The lower bound on P[ X >= threshold ] which the search would give for a single
+ * point rather than a box of intervals: the given coefficients are exact values
+ * of the largest a_i, and any variance they leave is made up by further a_i at
+ * most the smallest of them. As in can_be_resolved, we average the bound from
+ * the table over every choice of signs of the given coefficients.
+ */
+pub fn point_lower_bound(bounder: &Bounder, threshold: f64, coefs: &[f64]) -> f64 {
+    let mut total = 0.0;
+    for signs_code in 0..(1_u64 << coefs.len()) {
+	total += conditional_lower_bound(bounder, threshold, coefs, signs_code);
+    }
+    total / (1_u64 << coefs.len()) as f64
+}
+
+/**
+ * A single term of the average in point_lower_bound: the lower bound on
+ * P[ X >= threshold ] given the signs of the given coefficients, where a_i has
+ * sign -1 if bit i of signs_code is set and +1 otherwise. The rest of X must
+ * then make up the difference between threshold and the given part of the sum.
+ */
+pub fn conditional_lower_bound(bounder: &Bounder, threshold: f64, coefs: &[f64],
+			       signs_code: u64) -> f64 {
+    let remaining_var = 1.0 - coefs.iter().map(|a| a * a).sum::<f64>();
+    let max_remaining_coef = coefs.last().copied().unwrap_or(1.0);
+    let shift: f64 = coefs.iter().enumerate()
+	.map(|(i, a)| if (signs_code >> i) % 2 == 1 { *a } else { -*a })
+	.sum();
+    bounder.get_with_var(max_remaining_coef, threshold + shift, remaining_var, remaining_var)
+}
+
+/**
+ * Whether a single hypothesis was proved, and by how much it was (or wasn't)
+ * satisfied, where that makes sense.
+ */
+pub struct Outcome {
+    pub name: &'static str,
+    pub proved: bool,
+    pub margin: Option<f64>,
+}
+
+/**
+ * Everything that a run found, for use beyond the printed output.
+ */
+pub struct Report {
+    pub results: Results,
+    pub outcomes: Vec<Outcome>,
+    pub stats: SearchStats,
+}
+
+/**
+ * A complete record of a run, as a JSON document for archiving: the parameters of
+ * the case, everything found in each subcase, and the outcome of each hypothesis.
+ * This holds the same information as the printed results.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct RunDocument {
+    pub threshold: f64,
+    pub prob_cutoff: f64,
+    pub max_depth: usize,
+    pub denominator: u128,
+    pub bounds: Vec<Interval>,
+    pub restrictions: Vec<Restriction>,
+    pub hypotheses: Vec<Hypothesis>,
+    pub table_fingerprint: String,
+    pub subcases: Vec<SubcaseDocument>,
+    pub outcomes: Vec<OutcomeDocument>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OutcomeDocument {
+    pub name: String,
+    pub proved: bool,
+    pub margin: Option<f64>,
+}
+
+impl RunDocument {
+    pub fn new(report: &Report, case: &Case, bounder: &Bounder) -> RunDocument {
+	RunDocument {
+	    threshold: case.threshold,
+	    prob_cutoff: case.prob_cutoff,
+	    max_depth: case.max_depth,
+	    denominator: case.denominator,
+	    bounds: case.bounds.clone(),
+	    restrictions: case.restrictions.clone(),
+	    hypotheses: case.hypotheses.clone(),
+	    table_fingerprint: format!("{:016x}", bounder.fingerprint()),
+	    subcases: report.results.documents(&case.bounds),
+	    outcomes: report.outcomes.iter().map(|outcome| OutcomeDocument {
+		name: outcome.name.to_owned(),
+		proved: outcome.proved,
+		margin: outcome.margin,
+	    }).collect(),
+	}
+    }
+}
+
+/**
+ * What a run proved, in a form which can be saved and compared against later
+ * runs, so that a change which silently alters a proof is noticed. This is the
+ * interval of each a_i in each subcase, labelled as in the human-readable
+ * results, and whether each hypothesis was proved. Each is one line of text,
+ * e.g. "A 0 0.475 1", "small_a0 0 0 0.5" or "delta PASS".
+ */
+pub struct Baseline {
+    intervals: Vec<(String, usize, Interval)>,
+    verdicts: Vec<(String, bool)>,
+}
+
+impl Baseline {
+    pub fn new(report: &Report, case: &Case) -> Baseline {
+	Baseline {
+	    intervals: report.results.labelled_intervals(&case.bounds),
+	    verdicts: report.outcomes.iter()
+		.map(|outcome| (outcome.name.to_owned(), outcome.proved)).collect(),
+	}
+    }
+
+    /**
+     * Reads a baseline as written by its Display, or describes the first line
+     * which is not of that form.
+     */
+    pub fn of_string(text: &str) -> Result<Baseline, String> {
+	let mut intervals = vec![];
+	let mut verdicts = vec![];
+	for line in text.lines() {
+	    let malformed = || format!("Malformed line in baseline: {}", line);
+	    match line.split_whitespace().collect::<Vec<&str>>()[..] {
+		[label, index, lb, ub] => intervals.push((
+		    label.to_owned(),
+		    index.parse().map_err(|_| malformed())?,
+		    Interval { lb: lb.parse().map_err(|_| malformed())?,
+			       ub: ub.parse().map_err(|_| malformed())? },
+		)),
+		[name, "PASS"] => verdicts.push((name.to_owned(), true)),
+		[name, "FAIL"] => verdicts.push((name.to_owned(), false)),
+		[] => (),
+		_ => return Err(malformed()),
+	    }
+	}
+	Ok(Baseline { intervals, verdicts })
+    }
+}
+
+impl fmt::Display for Baseline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	let intervals = self.intervals.iter()
+	    .map(|(label, index, interval)| format!("{} {} {} {}", label, index,
+						      interval.lb, interval.ub));
+	let verdicts = self.verdicts.iter()
+	    .map(|(name, proved)| format!("{} {}", name, if *proved { "PASS" } else { "FAIL" }));
+	write!(f, "{}", intervals.chain(verdicts).collect::<Vec<String>>().join("\n"))
+    }
+}
+
+impl Baseline {
+    /**
+     * Describes every way in which this differs from the baseline, with the
+     * endpoints of intervals allowed to differ by up to tolerance.
+     */
+    pub fn divergences(&self, baseline: &Baseline, tolerance: f64) -> Vec<String> {
+	let mut divergences = vec![];
+	for (label, index, interval) in baseline.intervals.iter() {
+	    match self.intervals.iter().find(|x| x.0 == *label && x.1 == *index) {
+		Some((_, _, new)) if (new.lb - interval.lb).abs() > tolerance
+		    || (new.ub - interval.ub).abs() > tolerance => {
+			divergences.push(format!("subcase {}, a_{}: [{}, {}] was [{}, {}]",
+						 label, index, new.lb, new.ub,
+						 interval.lb, interval.ub));
+		    }
+		Some(_) => (),
+		None => divergences.push(format!("subcase {}, a_{}: missing, was [{}, {}]",
+						 label, index, interval.lb, interval.ub)),
+	    }
+	}
+	for (label, index, interval) in self.intervals.iter() {
+	    if !baseline.intervals.iter().any(|x| x.0 == *label && x.1 == *index) {
+		divergences.push(format!("subcase {}, a_{}: [{}, {}] was missing",
+					 label, index, interval.lb, interval.ub));
+	    }
+	}
+	if self.verdicts != baseline.verdicts {
+	    divergences.push(format!("hypotheses: {:?} were {:?}", self.verdicts,
+				     baseline.verdicts));
+	}
+	divergences
+    }
+}
+
+/**
+ * A single line summarising the outcome of each hypothesis, in a fixed format
+ * intended for scripts, e.g.
+ *     STATUS: delta=PASS margin=0.004; contradiction=FAIL
+ */
+fn status_line(outcomes: &[Outcome]) -> String {
+    let statuses = outcomes.iter().map(|outcome| {
+	let status = if outcome.proved { "PASS" } else { "FAIL" };
+	match outcome.margin {
+	    Some(margin) => format!("{}={} margin={}", outcome.name, status, margin),
+	    None => format!("{}={}", outcome.name, status),
+	}
+    }).collect::<Vec<String>>();
+    format!("STATUS: {}", statuses.join("; "))
+}
+
+/**
+ * Prints one row for each run of a sweep over some parameter of a case, such as
+ * the denominator, saying whether every hypothesis was proved and by how much
+ * each was or wasn't, so that it can be seen whether the results converge.
+ */
+pub fn print_sweep<T: fmt::Display>(parameter: &str, rows: &[(T, Report)]) {
+    println!("SWEEP RESULTS:");
+    println!("{}\tproved\tmargins", parameter);
+    for row in sweep_rows(rows) {
+	println!("{}", row);
+    }
+}
+
+/**
+ * The rows of the table printed by print_sweep, one for each run.
+ */
+fn sweep_rows<T: fmt::Display>(rows: &[(T, Report)]) -> Vec<String> {
+    rows.iter().map(|(value, report)| {
+	let proved = if report.outcomes.iter().all(|outcome| outcome.proved) {
+	    "PASS"
+	} else {
+	    "FAIL"
+	};
+	let margins = report.outcomes.iter().map(|outcome| match outcome.margin {
+	    Some(margin) => format!("{}={}", outcome.name, margin),
+	    None => outcome.name.to_owned(),
+	}).collect::<Vec<String>>();
+	format!("{}\t{}\t{}", value, proved, margins.join("; "))
+    }).collect()
+}
+
+/**
+ * Runs the case, using run, once with each max_depth from min_depth to max_depth,
+ * giving one row for each as for print_sweep.
+ */
+pub fn sweep_depths(case: &Case, min_depth: usize, max_depth: usize,
+		    run: impl Fn(&Case) -> Report) -> Vec<(usize, Report)> {
+    (min_depth..=max_depth).map(|depth| {
+	println!("SWEEP: running with depth {}", depth);
+	(depth, run(&case.with_max_depth(depth)))
+    }).collect()
+}
+
+/**
+ * The value in the first of the rows of a sweep at which every hypothesis was
+ * proved, if any.
+ */
+pub fn first_proved<T: Copy>(rows: &[(T, Report)]) -> Option<T> {
+    rows.iter()
+	.find(|(_value, report)| report.outcomes.iter().all(|outcome| outcome.proved))
+	.map(|(value, _report)| *value)
+}
+
+/**
+ * Prints how each run with a different resolve_epsilon differs from the run with
+ * the default, in its extrema or in which hypotheses it proved. If nothing changes
+ * when epsilon is halved, then the results are not limited by the tolerance.
+ */
+pub fn print_epsilon_sensitivity(case: &Case, base: &Report, runs: &[(f64, Report)]) {
+    let base = Baseline::new(base, case);
+    println!("EPSILON SENSITIVITY (default {}):", case.resolve_epsilon);
+    for (epsilon, report) in runs.iter() {
+	let divergences = Baseline::new(report, case).divergences(&base, 0.0);
+	if divergences.is_empty() {
+	    println!("epsilon = {}: no change", epsilon);
+	} else {
+	    println!("epsilon = {}: {} change(s)", epsilon, divergences.len());
+	    for divergence in divergences.iter() {
+		println!("    {}", divergence);
+	    }
+	}
+    }
+}
+
+/**
+ * Prints how many of the cases run by coverage had every hypothesis proved, and
+ * lists those which did not, as a measure of the strength of the table.
+ */
+pub fn print_coverage(rows: &[(String, Report)]) {
+    let failed = failed_cases(rows);
+    let proved = rows.len() - failed.len();
+    println!("COVERAGE: {} of {} cases proved ({:.1}%)", proved, rows.len(),
+	     100.0 * proved as f64 / rows.len().max(1) as f64);
+    for name in failed {
+	println!("FAILED: {}", name);
+    }
+}
+
+/**
+ * The names of the cases run by coverage which did not have every hypothesis proved.
+ */
+fn failed_cases(rows: &[(String, Report)]) -> Vec<&str> {
+    rows.iter()
+	.filter(|(_name, report)| !report.outcomes.iter().all(|outcome| outcome.proved))
+	.map(|(name, _report)| name.as_str())
+	.collect()
+}
+
+/**
+ * Lowering the threshold only makes each node easier to resolve, so if a case is
+ * proved at some threshold, then it is proved at every lower one too. Given
+ * whether it is proved at a threshold, this bisects between start and floor for
+ * the largest threshold at which it is proved, to within tolerance. Returns None
+ * if it is not proved even at floor.
+ */
+pub fn minimize_threshold(start: f64, floor: f64, tolerance: f64,
+			  mut proves: impl FnMut(f64) -> bool) -> Option<f64> {
+    if proves(start) {
+	return Some(start);
+    } else if !proves(floor) {
+	return None;
+    }
+    let (mut lower, mut upper) = (floor, start);
+    while upper - lower > tolerance {
+	let mid = (lower + upper) / 2.0;
+	if proves(mid) {
+	    lower = mid;
+	} else {
+	    upper = mid;
+	}
+    }
+    Some(lower)
+}
+
+/**
+ * Searches below each value of a_0 from min to max in parallel, as search_below
+ * does. Each subtree is merged into the shared results as soon as it is done, and
+ * after every that many subtrees, dump is given how many are done and the results
+ * so far. These only ever widen, as they come from more and more of the search.
+ */
+fn search_with_dumps(min: u128, max: u128, every: usize, new_search: impl Fn() -> Search + Sync,
+		     search_below: impl Fn(&mut Search, u128) + Sync,
+		     dump: impl Fn(usize, &Search) + Sync) -> Search {
+    let done = Mutex::new((0, new_search()));
+    (min..=max).into_par_iter().for_each(|numerator| {
+	let mut subtree = new_search();
+	search_below(&mut subtree, numerator);
+	let mut done = done.lock().unwrap();
+	let (count, so_far) = &mut *done;
+	so_far.absorb(subtree);
+	*count += 1;
+	if *count % every == 0 {
+	    dump(*count, so_far);
+	}
+    });
+    done.into_inner().unwrap().1
+}
+
 /**
  * Runs a simulation to produce a sequence of lower-bounds on the a_i for the problem
  *     P[ X >= bound ] >= prob_cutoff
  * i.e. if any a_i is outside the printed bounds, then the simulation here
  * has automatically proven that the above inequality must hold.
+ * If a prefix of numerators is given, then only the subtree below it is searched,
+ * which is useful for investigating why a case fails, but proves nothing about
+ * the case as a whole.
+ * If profile is set, then we also estimate how the time is split between
+ * can_be_resolved and the rest of the search.
+ * If approx is set, then can_be_resolved_approx is used instead, which is much
+ * faster at large depth but over-prunes, so a success is NOT a proof.
  */
-pub fn simulate(bounder: &Bounder, case: Case) {
+pub fn simulate(bounder: &Bounder, case: &Case, precision: Option<usize>, prefix: &[u128],
+		profile: bool, approx: bool, progress_dump: Option<usize>) -> Report {
+    if approx {
+	println!("APPROXIMATE MODE: the results below are NOT a valid proof!");
+    }
+    if bounder.is_near_top(case.threshold) {
+	println!("WARNING: the threshold {} is near the top of the table of bounds at {}, beyond which they are taken to be 0!",
+		 case.threshold, bounder.threshold_range().1);
+    }
+    if case.method != BoundMethod::Full {
+	println!("Using only the {:?} bounds, rather than the table.", case.method);
+    } else if !case.use_bernstein {
+	println!("Not using Bernstein's inequality or the Chernoff bound.");
+    }
+    if case.cannot_be_proved() {
+	// No node can be resolved, so the search would only expand every one of them.
+	println!("Skipping the search, as p = {} >= 1/2 means that nothing can be proved.",
+		 case.prob_cutoff);
+	let outcomes = case.hypotheses.iter()
+	    .map(|hypothesis| Outcome { name: hypothesis.name(), proved: false, margin: None })
+	    .collect::<Vec<_>>();
+	if !outcomes.is_empty() {
+	    println!("{}", status_line(&outcomes));
+	    println!("FAILED to prove all hypotheses!");
+	}
+	println!();
+	return Report { results: Results::new(case), outcomes,
+			stats: SearchStats::new(case.max_depth, case.restrictions.len()) };
+    }
     // We run with a fixed denominator.
-    let mut seq = Seq::new(0, case.denominator, case.max_depth);
-    let mut results = Results::new(&case);
-    let min = case.get_lower_bound(0);
-    let max = case.get_upper_bound(0);
-    for numerator in min..=max {
-        print!("{:.1}% ", (100.0 * (numerator - min) as f64) / ((1 + max - min) as f64));
-        let _ = io::stdout().flush();
-        seq.set(0, numerator);
-        simulate_rec(bounder, &mut seq, &mut results, &case, 1);
+    let mut search = Search::new(case, profile, approx);
+    if prefix.is_empty() {
+	let min = case.get_lower_bound(0);
+	let max = case.get_upper_bound(0);
+	search.stats.visited[0] += 1;
+	search.stats.include_expansion(0, min, max);
+	// The subtrees below each value of a_0 are searched in parallel.
+	let started = AtomicUsize::new(0);
+	let search_below = |search: &mut Search, numerator: u128| {
+	    let index = started.fetch_add(1, Ordering::Relaxed);
+	    print!("{:.1}% ", (100.0 * index as f64) / ((1 + max - min) as f64));
+	    let _ = io::stdout().flush();
+	    search.seq.set(0, numerator);
+	    search.run(bounder, case, 1);
+	};
+	let found = match progress_dump {
+	    None => (min..=max).into_par_iter()
+		.fold(|| Search::new(case, profile, approx), |mut search, numerator| {
+		    search_below(&mut search, numerator);
+		    search
+		})
+		.reduce(|| Search::new(case, profile, approx), Search::merge),
+	    Some(every) => {
+		search_with_dumps(min, max, every, || Search::new(case, profile, approx),
+				  search_below, |count, so_far| {
+		    println!();
+		    println!("RESULTS SO FAR, FROM {} OF {} VALUES OF a_0:", count, 1 + max - min);
+		    so_far.results.print(&case.bounds, precision);
+		    println!();
+		})
+	    }
+	};
+	search = search.merge(found);
+    } else {
+	println!("Searching only below the prefix {:?} (numerators over {})",
+		 prefix, case.denominator);
+	for (index, numerator) in prefix.iter().enumerate() {
+	    search.seq.set(index, *numerator);
+	}
+	search.run(bounder, case, prefix.len());
     }
+    let Search { results, stats, profile, .. } = search;
     println!("100.0%");
     println!();
+    println!("SEARCH TREE STATISTICS:");
+    stats.print();
+    println!();
+    if !case.restrictions.is_empty() {
+	println!("NODES PRUNED BY EACH RESTRICTION:");
+	stats.print_pruning(&case.restrictions);
+	println!();
+    }
+    if let Some(profile) = profile {
+	println!("PROFILE:");
+	profile.print();
+	println!();
+    }
+    println!("Table fingerprint: {:016x}", bounder.fingerprint());
+    println!();
     println!("MACHINE-READABLE RESULTS:");
-    results.print_machine(&case);
+    results.print_machine(case);
     println!();
     println!("HUMAN-READABLE RESULTS:");
-    results.print(&case.bounds);
+    results.print(&case.bounds, precision);
     use Hypothesis::*;
-    let mut all_hypotheses_proved = true;
+    let mut outcomes = vec![];
     println!();
     for hypothesis in case.hypotheses.iter() {
-	match hypothesis {
+	let outcome = match hypothesis {
 	    DeltaBound(target, delta_bound) => {
-		let max_delta = results.get_max_delta(*target, case.max_depth);
-		if max_delta + DELTA_ERROR <= *delta_bound {
+		let (max_delta, worst_index, worst_interval) =
+		    results.get_max_delta(*target, case.max_depth);
+		let proved = max_delta + DELTA_ERROR <= *delta_bound;
+		if proved {
 		    println!("We prove that delta <= {}. Actual max delta: {}",
-			     delta_bound, max_delta);
+			     delta_bound, format_float(max_delta, precision, f64::ceil));
 		} else {
 		    println!("delta not below bound: actual max delta = {} > {}",
-			     max_delta, delta_bound);
-		    all_hypotheses_proved = false;
+			     format_float(max_delta, precision, f64::ceil), delta_bound);
+		    println!("worst delta at a_{}: interval [{}, {}]", worst_index,
+			     format_float(worst_interval.lb, precision, f64::floor),
+			     format_float(worst_interval.ub, precision, f64::ceil));
+		}
+		Outcome { name: hypothesis.name(), proved, margin: Some(delta_bound - max_delta) }
+	    }
+	    WeightedDeltaBound(targets) => {
+		let (ratio, worst_index, worst_interval) =
+		    results.get_max_weighted_delta(targets, DELTA_ERROR, case.max_depth);
+		let proved = ratio <= 1.0;
+		if proved {
+		    println!("We prove that each a_i is near one of {:?}. Worst ratio: {}",
+			     targets, format_float(ratio, precision, f64::ceil));
+		} else {
+		    println!("not near targets: worst ratio of delta to tolerance = {} > 1",
+			     format_float(ratio, precision, f64::ceil));
+		    println!("worst at a_{}: interval [{}, {}]", worst_index,
+			     format_float(worst_interval.lb, precision, f64::floor),
+			     format_float(worst_interval.ub, precision, f64::ceil));
 		}
+		Outcome { name: hypothesis.name(), proved, margin: Some(1.0 - ratio) }
 	    }
 	    SumLowerBound(coefs, bound) => {
 		let sum_bound = results.get_sum_lower_bound(&coefs);
@@ -242,30 +1285,966 @@ pub fn simulate(bounder: &Bounder, case: Case) {
 				 coefs, bound, sum_bound);
 			proved = true;
 		    }
+		} else if results.is_contradiction() {
+		    println!("We prove for coefs {:?}, sum >= {}, as no sequence remains.",
+			     coefs, bound);
+		    proved = true;
 		}
 		if !proved {
 		    println!("sum {:?} not above bound: actual min sum = {:?} < {}",
 			     coefs, sum_bound, bound);
-		    all_hypotheses_proved = false;
 		}
+		Outcome { name: hypothesis.name(), proved, margin: sum_bound.map(|sum| sum - bound) }
 	    }
 	    Contradiction => {
-		if results.is_contradiction() {
+		let proved = results.is_contradiction();
+		if proved {
 		    println!("There is a contradiction, as required.")
 		} else {
 		    println!("There is no contradiction.");
-		    all_hypotheses_proved = false;
 		}
+		Outcome { name: hypothesis.name(), proved, margin: None }
 	    }
-	}
+	};
+	outcomes.push(outcome);
     }
     if case.hypotheses.len() >= 1 {
 	println!();
-	if all_hypotheses_proved {
+	println!("{}", status_line(&outcomes));
+	if outcomes.iter().all(|outcome| outcome.proved) {
 	    println!("All hypotheses proved!");
 	} else {
 	    println!("FAILED to prove all hypotheses!");
 	}
+	if !prefix.is_empty() {
+	    println!("(This is only for the subtree below the given prefix.)");
+	}
+	if approx {
+	    println!("(APPROXIMATE MODE: this over-prunes, so is NOT a proof.)");
+	}
     }
     println!();
+    Report { results, outcomes, stats }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prawitz::{Entries, THRESHOLD_RANGE};
+
+    /**
+     * A case with no bounds, restrictions or hypotheses.
+     */
+    fn plain_case(threshold: f64, prob_cutoff: f64, max_depth: usize, denominator: u128) -> Case {
+	Case { threshold, prob_cutoff, max_depth, denominator, bounds: vec![],
+	       restrictions: vec![], subcases: vec![], hypotheses: vec![],
+	       resolve_epsilon: RESOLVE_EPSILON, method: BoundMethod::Full,
+	       use_bernstein: true, tags: vec![] }
+    }
+
+    /**
+     * A table of the given value everywhere, which is impossible for values above
+     * 1/2 at positive thresholds.
+     */
+    fn constant_bounder(value: f64) -> Bounder {
+	let granularity = 10;
+	let max_bound = THRESHOLD_RANGE * granularity;
+	let bounds = vec![vec![value; 2 * max_bound]; granularity];
+	Bounder::new_manual(Entries::Double(bounds), granularity, granularity, max_bound)
+    }
+
+    /**
+     * A table which is 0 at negative thresholds, and 0.01 (a + 1) in row a at the
+     * rest, so that get shows which row it used there.
+     */
+    fn row_bounder() -> Bounder {
+	let granularity = 10;
+	let max_bound = THRESHOLD_RANGE * granularity;
+	let bounds = (0..granularity)
+	    .map(|a| (0..2 * max_bound)
+		 .map(|y| if y < max_bound { 0.0 } else { 0.01 * (a + 1) as f64 })
+		 .collect())
+	    .collect();
+	Bounder::new_manual(Entries::Double(bounds), granularity, granularity, max_bound)
+    }
+
+    #[test]
+    #[should_panic(expected = "impossible lower bound")]
+    fn poisoned_bounder_is_caught() {
+	let case = plain_case(0.5, 0.4, 2, 100);
+	let seq = Seq { numerators: vec![30, 0], denominator: 100 };
+	seq.can_be_resolved(&constant_bounder(0.9), &case, 1);
+    }
+
+    #[test]
+    fn sound_bounder_is_not_caught() {
+	let case = plain_case(0.5, 0.4, 2, 100);
+	let seq = Seq { numerators: vec![30, 0], denominator: 100 };
+	assert!(!seq.can_be_resolved(&constant_bounder(0.3), &case, 1));
+    }
+
+    #[test]
+    fn variance_cap_skips_only_resolved_numerators() {
+	let denominator = 20;
+	for (prefix, remaining) in [(vec![10, 10], 0), (vec![15, 5], 9), (vec![19, 6], 4)] {
+	    let mut seq = Seq { numerators: prefix.clone(), denominator };
+	    seq.numerators.push(0);
+	    let max = seq.max_numerator_in_variance(2, remaining);
+	    for numerator in 0..=denominator {
+		seq.set(2, numerator);
+		let fits = seq.numerators.iter().map(|x| x * x).sum::<u128>() + remaining
+		    <= denominator * denominator;
+		assert_eq!(fits, max.is_some_and(|max| numerator <= max),
+			   "prefix {:?}, numerator {}", prefix, numerator);
+	    }
+	}
+    }
+
+    #[test]
+    fn node_with_no_numerator_in_the_variance_is_resolved() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	// The threshold is too large for the table to resolve anything, but with
+	// a_0 in [0.8, 0.9] and a_2 at least 0.7, no a_1 keeps the variance at most 1.
+	let mut case = plain_case(2.5, 0.3, 3, 10);
+	case.bounds = vec![Interval::UNIT, Interval::UNIT, Interval { lb: 0.7, ub: 1.0 }];
+	let mut search = Search::new(&case, false, false);
+	search.seq.set(0, 8);
+	assert!(!search.seq.can_be_resolved(bounder, &case, 1));
+	search.run(bounder, &case, 1);
+	assert_eq!(search.stats.visited[1], 1);
+	assert_eq!(search.stats.resolved[1], 1);
+	assert_eq!(search.stats.children[1], 0);
+    }
+
+    #[test]
+    fn partial_variance_bounds_at_both_ends() {
+	// The first two a_i have partial variance between 0.45 and 0.65.
+	let seq = Seq { numerators: vec![6, 3, 0], denominator: 10 };
+	let survives = |lb: f64, ub: f64, depth: usize| {
+	    let restriction = Restriction::PartialVarianceBounds(2, Interval { lb, ub });
+	    seq.could_satisfy_restrictions(&[restriction], depth)
+	};
+	assert!(survives(0.65, 1.0, 2));
+	assert!(!survives(0.66, 1.0, 2));
+	assert!(survives(0.0, 0.45, 2));
+	assert!(!survives(0.0, 0.44, 2));
+	// Nothing is known until both a_i are set.
+	assert!(survives(0.66, 1.0, 1));
+    }
+
+    #[test]
+    fn equals_keeps_only_the_exact_numerator() {
+	let restrictions = [Restriction::Equals(1, 4)];
+	let survivors = (0..=10).filter(|numerator| {
+	    let seq = Seq { numerators: vec![10, *numerator], denominator: 10 };
+	    seq.could_satisfy_restrictions(&restrictions, 2)
+	}).collect::<Vec<u128>>();
+	assert_eq!(survivors, vec![4]);
+	let unset = Seq { numerators: vec![10, 0], denominator: 10 };
+	assert!(unset.could_satisfy_restrictions(&restrictions, 1));
+    }
+
+    #[test]
+    fn equal_prefix_keeps_only_equal_prefixes() {
+	let restrictions = [Restriction::EqualPrefix(3)];
+	for first in 0..5 {
+	    for second in 0..=first {
+		for third in 0..=second {
+		    let seq = Seq { numerators: vec![first, second, third, 0], denominator: 5 };
+		    assert_eq!(seq.could_satisfy_restrictions(&restrictions, 3),
+			       first == second && second == third);
+		}
+	    }
+	}
+	// Only the first three need be equal.
+	let longer = Seq { numerators: vec![3, 3, 3, 1], denominator: 5 };
+	assert!(longer.could_satisfy_restrictions(&restrictions, 4));
+    }
+
+    #[test]
+    fn zero_after_leaves_no_tail() {
+	let mut case = plain_case(0.5, 0.4, 4, 10);
+	case.restrictions.push(Restriction::ZeroAfter(2));
+	// Nothing is resolved by this table, so everything else survives.
+	let report = simulate(&constant_bounder(0.0), &case, None, &[], false, false, None);
+	let intervals = report.results.labelled_intervals(&case.bounds);
+	assert!(!intervals.is_empty());
+	for (_label, index, interval) in intervals {
+	    if index >= 2 {
+		assert_eq!((interval.lb, interval.ub), (0.0, 0.1), "a_{} is not zero", index);
+	    } else {
+		assert!(interval.ub > 0.5);
+	    }
+	}
+    }
+
+    #[test]
+    fn status_line_format() {
+	let mut case = plain_case(-0.5, 0.4, 2, 10);
+	case.hypotheses = vec![Hypothesis::Contradiction, Hypothesis::DeltaBound(0.3, 0.05)];
+	// Every node is resolved by this table.
+	let report = simulate(&constant_bounder(0.45), &case, None, &[], false, false, None);
+	assert_eq!(status_line(&report.outcomes), "STATUS: contradiction=PASS; delta=PASS margin=0.05");
+	case.hypotheses = vec![Hypothesis::Contradiction];
+	let report = simulate(&constant_bounder(0.0), &case, None, &[], false, false, None);
+	assert_eq!(status_line(&report.outcomes), "STATUS: contradiction=FAIL");
+    }
+
+    #[test]
+    fn case_which_cannot_be_proved_is_not_searched() {
+	let mut case = plain_case(0.5, 0.5, 3, 10);
+	case.hypotheses = vec![Hypothesis::Contradiction];
+	// A search would visit at least the root.
+	let report = simulate(&constant_bounder(0.45), &case, None, &[], false, false, None);
+	assert!(report.stats.visited.iter().all(|&visited| visited == 0));
+	assert_eq!(status_line(&report.outcomes), "STATUS: contradiction=FAIL");
+    }
+
+    #[test]
+    fn max_coef_lower_bound_in_either_order() {
+	let restrictions = [Restriction::MaxCoefLowerBound(0.6)];
+	let survives = |numerators: Vec<u128>| {
+	    let depth = numerators.len();
+	    Seq { numerators, denominator: 10 }.could_satisfy_restrictions(&restrictions, depth)
+	};
+	// Non-increasing, so only a_0 matters.
+	assert!(survives(vec![5, 3]));
+	assert!(!survives(vec![4, 3]));
+	// Otherwise the largest may come later.
+	assert!(survives(vec![3, 6]));
+	assert!(survives(vec![2, 5, 1]));
+	assert!(!survives(vec![3, 4, 1]));
+    }
+
+    #[test]
+    fn slack_is_negative_where_infeasible() {
+	let case = plain_case(0.5, 0.3, 2, 10);
+	let infeasible = slacks(&constant_bounder(0.2), &case);
+	assert_eq!(infeasible.len(), 10);
+	assert!(infeasible.iter().all(|(_a, slack)| (slack + 0.1).abs() < 1e-12));
+	let feasible = slacks(&constant_bounder(0.4), &case);
+	assert!(feasible.iter().all(|(_a, slack)| *slack > 0.0));
+    }
+
+    #[test]
+    fn machine_output_of_default_subcase_round_trips() {
+	use crate::file_io::{self, tests::*};
+	use_temp_root("machine");
+	let mut case = plain_case(0.5, 0.4, 3, 10);
+	case.subcases.push(Subcase { name: None,
+				     restrictions: vec![Restriction::of_string("Bounds(0, 0.6, 1)")] });
+	let bounder = constant_bounder(0.0);
+	let report = simulate(&bounder, &case, None, &[], false, false, None);
+	write_case_file("recycled", &report.results.default_machine_text(&case));
+	let recycled = file_io::get_case("recycled").unwrap();
+	let rerun = simulate(&bounder, &recycled, None, &[], false, false, None);
+	let original = report.results.documents(&case.bounds).pop().unwrap();
+	let again = rerun.results.documents(&recycled.bounds).pop().unwrap();
+	assert_eq!(original.contradiction, again.contradiction);
+	let ends = |intervals: &[Interval]| intervals.iter().map(|x| (x.lb, x.ub)).collect::<Vec<_>>();
+	assert_eq!(ends(&original.intervals), ends(&again.intervals));
+	// Without the Subcase line, the default subcase would take in all of a_0.
+	assert_eq!(ends(&again.intervals)[0], (0.0, 0.5));
+    }
+
+    #[test]
+    fn depth_zero_takes_the_largest_coefficient_to_be_one() {
+	let seq = Seq::new(0, 10, 2);
+	let bounder = constant_bounder(0.3);
+	assert_eq!(seq.box_lower_bound(&bounder, 0.5, BoundMethod::Full, true, 0),
+		   bounder.get(1.0, 0.5));
+	assert!(seq.can_be_resolved(&bounder, &plain_case(0.5, 0.25, 2, 10), 0));
+	assert!(!seq.can_be_resolved(&bounder, &plain_case(0.5, 0.35, 2, 10), 0));
+    }
+
+    #[test]
+    fn prefix_run_is_within_the_full_run() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let case = plain_case(0.3, 0.3, 4, 20);
+	let full = simulate(bounder, &case, None, &[], false, false, None);
+	let restricted = simulate(bounder, &case, None, &[12, 8], false, false, None);
+	let full_intervals = full.results.labelled_intervals(&case.bounds);
+	let restricted_intervals = restricted.results.labelled_intervals(&case.bounds);
+	assert!(!restricted_intervals.is_empty());
+	for (label, index, interval) in restricted_intervals {
+	    let (_label, _index, outer) = full_intervals.iter()
+		.find(|(full_label, full_index, _interval)| *full_label == label && *full_index == index)
+		.unwrap();
+	    assert!(outer.lb <= interval.lb && interval.ub <= outer.ub,
+		    "a_{} in {:?} is outside {:?}", index, interval, outer);
+	}
+    }
+
+    #[test]
+    fn variance_near_the_overflow_boundary() {
+	let denominator = (1 << 63) - 2;
+	assert!(square_sums_fit(denominator, 4));
+	let seq = Seq::new(denominator - 1, denominator, 4);
+	assert!(seq.max_variance() > 3.99 && seq.min_variance() > 3.99);
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow in a sum of squares")]
+    fn overflowing_square_sums_are_caught() {
+	square_sum([1 << 63, 1 << 63, 1 << 63, 1 << 63].into_iter());
+    }
+
+    #[test]
+    fn profile_counters_are_populated() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let case = plain_case(0.3, 0.3, 4, 20);
+	let mut search = Search::new(&case, true, false);
+	for numerator in case.get_lower_bound(0)..=case.get_upper_bound(0) {
+	    search.seq.set(0, numerator);
+	    search.run(bounder, &case, 1);
+	}
+	let profile = search.profile.unwrap();
+	assert!(profile.resolve_calls >= PROFILE_SAMPLE_EVERY, "{} calls", profile.resolve_calls);
+	assert!(profile.get_with_var_calls > 0);
+	// The count of calls on this thread may not start at 0, as other tests share it.
+	assert!(profile.sampled_calls >= profile.resolve_calls / PROFILE_SAMPLE_EVERY);
+	assert!(profile.sampled_calls <= profile.resolve_calls);
+	assert!(profile.search_time > Duration::ZERO);
+	assert!(profile.search_time >= profile.sampled_time);
+	assert!(Search::new(&case, false, false).profile.is_none());
+    }
+
+    #[test]
+    fn total_sum_upper_bound_at_the_boundary() {
+	let restrictions = [Restriction::TotalSumUpperBound(1.0)];
+	let satisfies = |numerators: Vec<u128>, depth: usize| {
+	    Seq { numerators, denominator: 10 }.could_satisfy_restrictions(&restrictions, depth)
+	};
+	// The smallest possible sum is exactly the bound, so is allowed.
+	assert!(satisfies(vec![5, 3, 2, 0], 3));
+	assert!(!satisfies(vec![5, 3, 3, 0], 3));
+	// Even at depth 2 the sum of those set so far is too large.
+	assert!(!satisfies(vec![6, 5, 0, 0], 2));
+	// Numerators not yet set do not count.
+	assert!(satisfies(vec![6, 4, 9, 9], 2));
+	// This is the same as the initial sum bound for the whole sequence.
+	for numerators in [vec![5, 3, 2, 0], vec![5, 3, 3, 0], vec![6, 5, 0, 0], vec![6, 4, 9, 9]] {
+	    for depth in 0..=4 {
+		let seq = Seq { numerators: numerators.clone(), denominator: 10 };
+		assert_eq!(seq.could_satisfy_restrictions(&restrictions, depth),
+			   seq.could_satisfy_restrictions(&[Restriction::InitialSumUpperBound(4, 1.0)], depth));
+	    }
+	}
+    }
+
+    #[test]
+    fn point_lower_bound_of_a_whole_sequence() {
+	// With a_0 = 0.8 and a_1 = 0.6 there is no variance left, so X takes the
+	// values +-1.4 and +-0.2 with probability 1/4 each.
+	let bounder = crate::prawitz::tests::small_bounder();
+	assert_eq!(point_lower_bound(bounder, -0.5, &[0.8, 0.6]), 0.75);
+	assert_eq!(point_lower_bound(bounder, 0.1, &[0.8, 0.6]), 0.5);
+	assert_eq!(point_lower_bound(bounder, 1.5, &[0.8, 0.6]), 0.0);
+	// With no coefficients given, this is just the table.
+	assert_eq!(point_lower_bound(bounder, 0.3, &[]), bounder.get(1.0, 0.3));
+    }
+
+    #[test]
+    fn weakened_table_diverges_from_baseline() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let case = plain_case(0.3, 0.3, 4, 20);
+	let report = simulate(bounder, &case, None, &[], false, false, None);
+	let saved = Baseline::of_string(&Baseline::new(&report, &case).to_string()).unwrap();
+	assert!(Baseline::new(&report, &case).divergences(&saved, 0.0).is_empty());
+	let weakened = crate::prawitz::tests::entries(bounder).iter()
+	    .map(|row| row.iter().map(|x| 0.9 * x).collect())
+	    .collect();
+	let weakened = Bounder::new_manual(Entries::Double(weakened), 50, 50, THRESHOLD_RANGE * 50);
+	let report = simulate(&weakened, &case, None, &[], false, false, None);
+	assert!(!Baseline::new(&report, &case).divergences(&saved, 1e-9).is_empty());
+    }
+
+    #[test]
+    fn malformed_baseline_is_an_error() {
+	assert!(Baseline::of_string("A 0 0.4 1\ndelta PASS\n").is_ok());
+	assert_eq!(Baseline::of_string("A 0 0.4\n").err().unwrap(),
+		   "Malformed line in baseline: A 0 0.4");
+	assert!(Baseline::of_string("A x 0.4 1").is_err());
+	assert!(Baseline::of_string("delta MAYBE").is_err());
+    }
+
+    #[test]
+    fn close_coefficients_prunes_far_apart_pairs() {
+	let satisfies = |restriction: Restriction, numerators: Vec<u128>, depth: usize| {
+	    Seq { numerators, denominator: 10 }.could_satisfy_restrictions(&[restriction], depth)
+	};
+	for restriction in [Restriction::CloseCoefficients(0, 2, 0.2),
+			    Restriction::CloseCoefficients(2, 0, 0.2)] {
+	    // a_0 in [0.7, 0.8] and a_2 in [0.4, 0.5] could be 0.2 apart.
+	    assert!(satisfies(restriction.clone(), vec![7, 5, 4], 3));
+	    // a_0 in [0.8, 0.9] and a_2 in [0.4, 0.5] are at least 0.3 apart.
+	    assert!(!satisfies(restriction.clone(), vec![8, 5, 4], 3));
+	    assert!(!satisfies(restriction.clone(), vec![9, 9, 0], 3));
+	    // Nothing is known until both are set.
+	    assert!(satisfies(restriction, vec![9, 9, 0], 2));
+	}
+    }
+
+    #[test]
+    fn between_only_checks_within_the_window() {
+	let inner = Restriction::TotalSumUpperBound(0.5);
+	let between = Restriction::Between(2, 3, Box::new(inner.clone()));
+	let seq = Seq { numerators: vec![9; 5], denominator: 10 };
+	// The sum of those set is too large from depth 1 on, but Between only
+	// prunes at depths 2 and 3.
+	for depth in 1..=5 {
+	    assert!(!seq.could_satisfy_restrictions(std::slice::from_ref(&inner), depth));
+	    assert_eq!(seq.could_satisfy_restrictions(std::slice::from_ref(&between), depth),
+		       !(2..=3).contains(&depth), "depth {}", depth);
+	}
+	// Within the window it agrees with the inner restriction.
+	let small = Seq { numerators: vec![1; 5], denominator: 10 };
+	for depth in 2..=3 {
+	    assert!(small.could_satisfy_restrictions(std::slice::from_ref(&between), depth));
+	}
+    }
+
+    #[test]
+    fn distinct_values_beyond_the_bound_are_rejected() {
+	let restrictions = [Restriction::DistinctValuesUpperBound(2)];
+	let satisfies = |numerators: Vec<u128>, depth: usize| {
+	    Seq { numerators, denominator: 10 }.could_satisfy_restrictions(&restrictions, depth)
+	};
+	assert!(satisfies(vec![5, 5, 3, 3], 4));
+	assert!(satisfies(vec![7, 7, 7, 7], 4));
+	assert!(!satisfies(vec![5, 4, 3, 3], 4));
+	assert!(!satisfies(vec![6, 5, 5, 1], 4));
+	// Those not yet set do not count, so a third value is only rejected once set.
+	assert!(satisfies(vec![5, 4, 3, 3], 2));
+	assert!(!satisfies(vec![5, 4, 3, 3], 3));
+    }
+
+    #[test]
+    fn from_coefficients_brackets_the_variance() {
+	for coefs in [vec![0.6, 0.5, 0.4, 0.3, 0.2], vec![1.0], vec![0.7, 0.7, 0.1],
+		      vec![0.55, 0.45, 0.45, 0.35, 0.25, 0.15, 0.05]] {
+	    let variance = coefs.iter().map(|a| a * a).sum::<f64>();
+	    for denominator in [10, 100, 1000, 1 << 40] {
+		let seq = Seq::from_coefficients(&coefs, denominator);
+		assert_eq!(seq.numerators.len(), coefs.len());
+		// On a boundary, as with 0.7 * 0.7 < 0.49, these can differ by rounding.
+		assert!(seq.min_variance() <= variance + 1e-12 && variance <= seq.max_variance(),
+			"{:?} over {}", coefs, denominator);
+		for (index, a) in coefs.iter().enumerate() {
+		    assert!(seq.get_interval(index).contains(*a));
+		    assert!(a - seq.get_min(index) < 1.0 / denominator as f64 + 1e-12);
+		}
+	    }
+	}
+	// Each coefficient on a boundary goes in the interval above, but 1 in the last.
+	assert_eq!(Seq::from_coefficients(&[1.0, 0.5, 0.0], 10).numerators, vec![9, 5, 0]);
+    }
+
+    #[test]
+    fn conditional_bounds_average_to_the_point_bound() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	for coefs in [vec![0.8, 0.6], vec![0.6, 0.5, 0.4], vec![0.5, 0.5, 0.3, 0.2, 0.1]] {
+	    for threshold in [-0.5, 0.0, 0.3, 0.7] {
+		let terms = (0..1_u64 << coefs.len())
+		    .map(|signs_code| conditional_lower_bound(bounder, threshold, &coefs, signs_code))
+		    .collect::<Vec<f64>>();
+		let average = terms.iter().sum::<f64>() / terms.len() as f64;
+		assert_eq!(average, point_lower_bound(bounder, threshold, &coefs));
+		// The bound over a box of the search is at most that at any point in it.
+		let seq = Seq::from_coefficients(&coefs, 100);
+		let over_box = seq.box_lower_bound(bounder, threshold, BoundMethod::Full, true,
+						   coefs.len());
+		assert!(over_box <= average, "{:?} at {}: {} > {}", coefs, threshold,
+			over_box, average);
+	    }
+	}
+    }
+
+    #[test]
+    fn tail_max_upper_bound_prunes_a_large_tail_coefficient() {
+	let restrictions = [Restriction::TailMaxUpperBound(1, 0.4)];
+	let satisfies = |numerators: Vec<u128>, depth: usize| {
+	    Seq { numerators, denominator: 10 }.could_satisfy_restrictions(&restrictions, depth)
+	};
+	// a_0 itself is not bounded, and a_1 in [0.4, 0.5] could be 0.4.
+	assert!(satisfies(vec![9, 4, 2], 3));
+	assert!(!satisfies(vec![9, 5, 2], 3));
+	// The a_i need not be non-increasing: a large a_2 is pruned even though a_0
+	// and a_1 are small, but only once it is set.
+	assert!(!satisfies(vec![3, 1, 6], 3));
+	assert!(satisfies(vec![3, 1, 6], 2));
+	assert!(satisfies(vec![3, 4, 1, 0], 4));
+    }
+
+    #[test]
+    fn deepest_depth_of_a_sweep_matches_a_direct_run() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.3, 0.3, 2, 20);
+	case.hypotheses = vec![Hypothesis::DeltaBound(0.25, 0.3)];
+	let run = |case: &Case| simulate(bounder, case, None, &[], false, false, None);
+	let rows = sweep_depths(&case, 2, 4, run);
+	assert_eq!(rows.iter().map(|(depth, _report)| *depth).collect::<Vec<_>>(), [2, 3, 4]);
+	let direct = run(&case.with_max_depth(4));
+	let deepest = &rows[2].1;
+	assert_eq!(serde_json::to_string(&deepest.results.documents(&case.bounds)).unwrap(),
+		   serde_json::to_string(&direct.results.documents(&case.bounds)).unwrap());
+	assert_eq!(deepest.stats.to_csv(), direct.stats.to_csv());
+	assert_eq!(sweep_rows(&rows[2..]), sweep_rows(&[(4, direct)]));
+	let expected = rows.iter().find(|(_depth, report)| report.outcomes[0].proved)
+	    .map(|(depth, _report)| *depth);
+	assert_eq!(first_proved(&rows), expected);
+    }
+
+    #[test]
+    fn box_lower_bound_of_two_coefficients_by_hand() {
+	// Here a_0 is in [0.6, 0.7] and a_1 in [0.3, 0.4], so the remaining variance
+	// is in [1 - 0.65, 1 - 0.45] = [0.35, 0.55], and the remaining coefficients
+	// are at most 0.4. Below 0 the table gives nothing, so the bound is Chernoff's
+	// 1 - exp(-t^2 / 2 max_var), dividing the cutoff by the larger deviation.
+	let bounder = row_bounder();
+	let seq = Seq { numerators: vec![6, 3], denominator: 10 };
+	let chernoff = |t: f64| 1.0 - (-t * t / (2.0 * 0.55)).exp();
+	let box_bound = |threshold: f64| seq.box_lower_bound(&bounder, threshold, BoundMethod::Full,
+							    true, 2);
+	// With signs (+, +) the rest of X must reach -0.5 - 0.6 - 0.3 = -1.4, with
+	// (-, +) it must reach -0.5 + 0.7 - 0.3 = -0.1, with (+, -) it must reach
+	// -0.5 - 0.6 + 0.4 = -0.7, and with (-, -) it must reach -0.5 + 0.7 + 0.4 = 0.6.
+	// At 0.6, dividing by the smaller deviation sqrt(0.35) and scaling the largest
+	// coefficient 0.4 the same way gives a = 0.68, which is in row 7.
+	let expected = (chernoff(-1.4) + chernoff(-0.1) + chernoff(-0.7) + 0.08) / 4.0;
+	assert!((box_bound(-0.5) - expected).abs() < 1e-12, "{} != {}", box_bound(-0.5), expected);
+	// With threshold 0.2 only (+, +) needs a negative part of the sum, -0.7, and the
+	// other three need 0.6, 0 and 1.3, all of which use row 7.
+	let expected = (chernoff(-0.7) + 3.0 * 0.08) / 4.0;
+	assert!((box_bound(0.2) - expected).abs() < 1e-12, "{} != {}", box_bound(0.2), expected);
+	// Each term is the bound for the corner of the box which is hardest for that
+	// sign pattern, which is at most the bound at any point of the box.
+	let small = crate::prawitz::tests::small_bounder();
+	for threshold in [-0.5, 0.0, 0.2, 0.6] {
+	    let over_box = seq.box_lower_bound(small, threshold, BoundMethod::Full, true, 2);
+	    for a_0 in [0.6, 0.63, 0.67, 0.7] {
+		for a_1 in [0.3, 0.35, 0.4] {
+		    assert!(over_box <= point_lower_bound(small, threshold, &[a_0, a_1]),
+			    "[{}, {}] at {}", a_0, a_1, threshold);
+		}
+	    }
+	}
+    }
+
+    #[test]
+    fn sweep_has_one_row_per_denominator() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let case = plain_case(0.3, 0.3, 3, 20);
+	let rows = [10, 20, 40].into_iter()
+	    .map(|denominator| (denominator, simulate(bounder, &case.with_denominator(denominator),
+						       None, &[], false, false, None)))
+	    .collect::<Vec<_>>();
+	let lines = sweep_rows(&rows);
+	assert_eq!(lines.len(), 3);
+	for (line, denominator) in lines.iter().zip([10, 20, 40]) {
+	    assert!(line.starts_with(&format!("{}\t", denominator)), "row {:?}", line);
+	}
+    }
+
+    #[test]
+    fn parallel_subcase_extrema_equal_serial_ones() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.3, 0.3, 4, 20);
+	case.subcases = vec![
+	    Subcase { name: None, restrictions: vec![Restriction::MaxCoefLowerBound(0.6)] },
+	    Subcase { name: Some("small".to_owned()),
+		      restrictions: vec![Restriction::InitialSumUpperBound(2, 1.0)] },
+	];
+	case.hypotheses = vec![Hypothesis::SumLowerBound(vec![1, 1], 0.5)];
+	let mut serial = Search::new(&case, false, false);
+	for numerator in case.get_lower_bound(0)..=case.get_upper_bound(0) {
+	    serial.seq.set(0, numerator);
+	    serial.run(bounder, &case, 1);
+	}
+	let parallel = simulate(bounder, &case, None, &[], false, false, None);
+	let as_json = |results: &Results| {
+	    serde_json::to_string(&results.documents(&case.bounds)).unwrap()
+	};
+	assert_eq!(as_json(&parallel.results), as_json(&serial.results));
+    }
+
+    #[test]
+    fn progress_is_dumped_every_n_values_of_a_0() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let case = plain_case(0.3, 0.3, 3, 20);
+	let (min, max) = (case.get_lower_bound(0), case.get_upper_bound(0));
+	let as_json = |results: &Results| {
+	    serde_json::to_string(&results.documents(&case.bounds)).unwrap()
+	};
+	let search_below = |search: &mut Search, numerator: u128| {
+	    search.seq.set(0, numerator);
+	    search.run(bounder, &case, 1);
+	};
+	let whole = search_with_dumps(min, max, usize::MAX, || Search::new(&case, false, false),
+				      search_below, |_count, _so_far| ());
+	let dumps = Mutex::new(vec![]);
+	let found = search_with_dumps(min, max, 3, || Search::new(&case, false, false),
+				      search_below, |count, so_far| {
+	    dumps.lock().unwrap().push((count, as_json(&so_far.results)));
+	});
+	let dumps = dumps.into_inner().unwrap();
+	let values = (1 + max - min) as usize;
+	assert!(values >= 6);
+	assert_eq!(dumps.iter().map(|(count, _)| *count).collect::<Vec<_>>(),
+		   (1..=values / 3).map(|i| 3 * i).collect::<Vec<_>>());
+	assert_eq!(as_json(&found.results), as_json(&whole.results));
+	if values.is_multiple_of(3) {
+	    assert_eq!(dumps.last().unwrap().1, as_json(&whole.results));
+	}
+    }
+
+    #[test]
+    fn reusing_a_search_gives_the_same_results() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let case = plain_case(0.3, 0.3, 4, 20);
+	let as_json = |search: &Search| {
+	    serde_json::to_string(&search.results.documents(&case.bounds)).unwrap()
+	};
+	let prefixes = [vec![12], vec![9, 7], vec![12], vec![15]];
+	let mut reused = Search::new(&case, false, false);
+	let mut fresh = Search::new(&case, false, false);
+	for prefix in prefixes.iter() {
+	    for (index, numerator) in prefix.iter().enumerate() {
+		reused.seq.set(index, *numerator);
+	    }
+	    reused.run(bounder, &case, prefix.len());
+	    assert!(reused.seq.numerators.iter().all(|numerator| *numerator == 0));
+	    let mut alone = Search::new(&case, false, false);
+	    for (index, numerator) in prefix.iter().enumerate() {
+		alone.seq.set(index, *numerator);
+	    }
+	    alone.run(bounder, &case, prefix.len());
+	    fresh = fresh.merge(alone);
+	}
+	assert_eq!(as_json(&reused), as_json(&fresh));
+	assert_eq!(reused.stats.to_csv(), fresh.stats.to_csv());
+    }
+
+    #[test]
+    fn tsv_has_a_row_per_interval_with_every_field() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.3, 0.3, 4, 20);
+	case.subcases = vec![
+	    Subcase { name: None, restrictions: vec![Restriction::MaxCoefLowerBound(0.6)] },
+	    Subcase { name: Some("small".to_owned()),
+		      restrictions: vec![Restriction::InitialSumUpperBound(2, 1.0)] },
+	];
+	case.hypotheses = vec![Hypothesis::DeltaBound(0.25, 0.3),
+			       Hypothesis::SumLowerBound(vec![1, 1], 0.5),
+			       Hypothesis::SumLowerBound(vec![1, -1], 0.0)];
+	let results = simulate(bounder, &case, None, &[], false, false, None).results;
+	let tsv = results.to_tsv(&case);
+	let mut lines = tsv.lines();
+	assert_eq!(lines.next(), Some("subcase\tindex\tlb\tub\tdelta(0.25)\tsum[1, 1]\tsum[1, -1]"));
+	let rows = lines.map(|line| line.split('\t').collect::<Vec<&str>>()).collect::<Vec<_>>();
+	let intervals = results.labelled_intervals(&case.bounds);
+	assert!(!intervals.is_empty());
+	assert_eq!(rows.len(), intervals.len());
+	for (row, (label, index, interval)) in rows.iter().zip(intervals.iter()) {
+	    assert_eq!(row.len(), 7, "{:?}", row);
+	    assert_eq!((row[0], row[1].parse::<usize>().unwrap()), (label.as_str(), *index));
+	    assert_eq!(row[2].parse::<f64>().unwrap(), interval.lb);
+	    assert_eq!(row[3].parse::<f64>().unwrap(), interval.ub);
+	    assert!(row[4].parse::<f64>().unwrap() >= 0.0);
+	}
+    }
+
+    #[test]
+    fn run_document_round_trips() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.3, 0.3, 3, 20);
+	case.subcases = vec![Subcase { name: Some("big".to_owned()),
+				       restrictions: vec![Restriction::MaxCoefLowerBound(0.6)] }];
+	case.hypotheses = vec![Hypothesis::SumLowerBound(vec![1, 1], 0.5)];
+	let report = simulate(bounder, &case, None, &[], false, false, None);
+	let text = serde_json::to_string(&RunDocument::new(&report, &case, bounder)).unwrap();
+	let document: RunDocument = serde_json::from_str(&text).unwrap();
+	assert_eq!(serde_json::to_string(&document).unwrap(), text);
+	assert_eq!(document.denominator, 20);
+	assert_eq!(document.table_fingerprint, format!("{:016x}", bounder.fingerprint()));
+	let labels = document.subcases.iter().map(|subcase| subcase.label.as_str()).collect::<Vec<_>>();
+	assert_eq!(labels, ["big", "B"]);
+	assert_eq!(document.outcomes.len(), 1);
+	assert_eq!(document.outcomes[0].proved, report.outcomes[0].proved);
+    }
+
+    #[test]
+    fn approx_never_keeps_what_exact_prunes() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut resolved = 0;
+	for case in [plain_case(0.3, 0.3, 3, 10), plain_case(-0.5, 0.6, 3, 10)] {
+	    for first in 0..10 {
+		for second in 0..=first {
+		    for third in 0..=second {
+			let seq = Seq { numerators: vec![first, second, third], denominator: 10 };
+			for depth in 1..=3 {
+			    if seq.can_be_resolved(bounder, &case, depth) {
+				resolved += 1;
+				assert!(seq.can_be_resolved_approx(bounder, &case, depth),
+					"{:?} at depth {}", seq.numerators, depth);
+			    }
+			}
+		    }
+		}
+	    }
+	}
+	assert!(resolved > 0);
+    }
+
+    #[test]
+    fn prefix_variance_fraction_at_the_boundary() {
+	// With a_0 in [0.6, 0.7], the first coefficient carries at most 0.49 of the variance.
+	let seq = Seq { numerators: vec![6, 3, 0], denominator: 10 };
+	let survives = |depth: usize, frac: f64, at_depth: usize| {
+	    let restriction = Restriction::PrefixVarianceFractionLowerBound(depth, frac);
+	    seq.could_satisfy_restrictions(&[restriction], at_depth)
+	};
+	// A largest prefix variance of exactly frac survives.
+	assert_eq!(seq.max_partial_variance(1), 0.49);
+	assert!(survives(1, 0.49, 1));
+	assert!(!survives(1, 0.5, 1));
+	// Adding a_1 in [0.3, 0.4] brings the prefix up to at most 0.65.
+	assert_eq!(seq.max_partial_variance(2), 0.65);
+	assert!(survives(2, 0.65, 2));
+	assert!(!survives(2, 0.66, 2));
+	// Nothing is known until the prefix is set.
+	assert!(survives(2, 0.66, 1));
+    }
+
+    #[test]
+    fn epsilon_sensitivity_near_the_tolerance() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let bound_at = |numerator: u128| {
+	    Seq { numerators: vec![numerator], denominator: 10 }
+		.box_lower_bound(bounder, 0.3, BoundMethod::Full, true, 1)
+	};
+	// Put the cutoff just under the weakest bound for a_0 < 0.9, so that doubling
+	// epsilon stops that node being resolved, but halving it changes nothing.
+	let weakest = (0..9).map(bound_at).fold(f64::INFINITY, f64::min);
+	assert!((0..9).filter(|numerator| bound_at(*numerator) < weakest + 3.0 * RESOLVE_EPSILON)
+		.count() == 1);
+	let mut case = plain_case(0.3, weakest - 1.5 * RESOLVE_EPSILON, 1, 10);
+	case.bounds = vec![Interval { lb: 0.0, ub: 0.85 }];
+	let base = simulate(bounder, &case, None, &[], false, false, None);
+	let changes = |factor: f64| {
+	    let report = simulate(bounder, &case.with_resolve_epsilon(RESOLVE_EPSILON * factor),
+				  None, &[], false, false, None);
+	    Baseline::new(&report, &case).divergences(&Baseline::new(&base, &case), 0.0)
+	};
+	assert!(changes(0.5).is_empty());
+	assert!(!changes(2.0).is_empty());
+    }
+
+    #[test]
+    fn linear_bounds_keep_the_band() {
+	// -0.1 <= a_0 - a_1 <= 0.1
+	let restrictions = [Restriction::LinearBounds(vec![(0, 1.0), (1, -1.0)], -0.1, 0.1)];
+	for first in 0..10 {
+	    for second in 0..=first {
+		let seq = Seq { numerators: vec![first, second], denominator: 10 };
+		// The difference lies in [first - second - 1, first - second + 1] / 10.
+		match first - second {
+		    0 | 1 => assert!(seq.could_satisfy_restrictions(&restrictions, 2)),
+		    2 => (),
+		    _ => assert!(!seq.could_satisfy_restrictions(&restrictions, 2),
+				 "{:?} kept", seq.numerators),
+		}
+		// Nothing is known until both are set.
+		assert!(seq.could_satisfy_restrictions(&restrictions, 1));
+	    }
+	}
+	// A band on a single weighted term.
+	let restrictions = [Restriction::LinearBounds(vec![(0, -2.0)], -1.0, -0.5)];
+	let survives = |numerator: u128| {
+	    Seq { numerators: vec![numerator], denominator: 10 }
+		.could_satisfy_restrictions(&restrictions, 1)
+	};
+	assert_eq!((0..10).filter(|numerator| survives(*numerator)).collect::<Vec<_>>(),
+		   [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn bernstein_alone_resolves_a_strict_subset() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut only_full = 0;
+	for (threshold, prob_cutoff) in [(0.3, 0.3), (-0.5, 0.6), (-1.0, 0.8)] {
+	    let full = plain_case(threshold, prob_cutoff, 3, 10);
+	    let bernstein = Case { method: BoundMethod::Bernstein, ..full.clone() };
+	    for first in 0..10 {
+		for second in 0..=first {
+		    for third in 0..=second {
+			let seq = Seq { numerators: vec![first, second, third], denominator: 10 };
+			for depth in 1..=3 {
+			    let by_full = seq.can_be_resolved(bounder, &full, depth);
+			    if seq.can_be_resolved(bounder, &bernstein, depth) {
+				assert!(by_full, "{:?} at depth {}", seq.numerators, depth);
+			    } else if by_full {
+				only_full += 1;
+			    }
+			}
+		    }
+		}
+	    }
+	    // And so every interval of the Bernstein-only run contains that of the full run.
+	    let full_run = simulate(bounder, &full, None, &[], false, false, None);
+	    let bernstein_run = simulate(bounder, &bernstein, None, &[], false, false, None);
+	    let bernstein_intervals = bernstein_run.results.labelled_intervals(&full.bounds);
+	    for (label, index, interval) in full_run.results.labelled_intervals(&full.bounds) {
+		let wider = bernstein_intervals.iter()
+		    .find(|(other_label, other_index, _)| *other_label == label && *other_index == index)
+		    .unwrap();
+		assert!(wider.2.lb <= interval.lb && interval.ub <= wider.2.ub);
+	    }
+	}
+	assert!(only_full > 0);
+    }
+
+    #[test]
+    fn geometric_decay_survivors_decay() {
+	let restrictions = [Restriction::GeometricDecay(1, 0.5)];
+	let mut pruned = 0;
+	for first in 0..20 {
+	    for second in 0..=first {
+		for third in 0..=second {
+		    for fourth in 0..=third {
+			let numerators = vec![first, second, third, fourth];
+			let seq = Seq { numerators: numerators.clone(), denominator: 20 };
+			// a_{i+1} <= a_i / 2 is possible for each i >= 1 exactly when the
+			// bottom of the interval of a_{i+1} is at most half the top of a_i's.
+			let decays = (1..3).all(|i| 2 * numerators[i + 1] <= numerators[i] + 1);
+			assert_eq!(seq.could_satisfy_restrictions(&restrictions, 4), decays,
+				   "{:?}", numerators);
+			pruned += !decays as usize;
+			// a_1 need not be at most a_0 / 2, and a_3 is not checked until it is set.
+			assert!(seq.could_satisfy_restrictions(&restrictions, 2));
+			assert_eq!(seq.could_satisfy_restrictions(&restrictions, 3),
+				   2 * third <= second + 1);
+		    }
+		}
+	    }
+	}
+	assert!(pruned > 0);
+    }
+
+    #[test]
+    fn stats_csv_has_a_row_per_depth_matching_the_totals() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.3, 0.3, 4, 20);
+	case.restrictions = vec![Restriction::InitialSumUpperBound(2, 1.2)];
+	let stats = simulate(bounder, &case, None, &[], false, false, None).stats;
+	let csv = stats.to_csv();
+	let mut lines = csv.lines();
+	assert_eq!(lines.next(), Some("depth,visited,pruned,resolved,expanded,leaves"));
+	let rows = lines.map(|line| line.split(',').map(|x| x.parse().unwrap()).collect::<Vec<u128>>())
+	    .collect::<Vec<_>>();
+	assert_eq!(rows.len(), case.max_depth + 1);
+	for (depth, row) in rows.iter().enumerate() {
+	    assert_eq!(row[0], depth as u128);
+	    assert!(row[2] + row[3] + row[4] + row[5] <= row[1], "depth {}: {:?}", depth, row);
+	    if depth > 0 {
+		assert_eq!(row[1], stats.children[depth - 1]);
+	    }
+	}
+	let pruned: u128 = rows.iter().map(|row| row[2]).sum();
+	assert!(pruned > 0);
+	assert_eq!(pruned, stats.pruned_by.iter().sum::<u128>());
+	assert_eq!(rows[case.max_depth][4], 0);
+    }
+
+    #[test]
+    fn prunes_are_counted_against_the_first_failing_restriction() {
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.3, 0.3, 4, 20);
+	// The first can never fail, and the last only fails where the second does.
+	case.restrictions = vec![Restriction::TotalSumUpperBound(100.0),
+				 Restriction::InitialSumUpperBound(2, 1.2),
+				 Restriction::InitialSumUpperBound(2, 1.5)];
+	let stats = simulate(bounder, &case, None, &[], false, false, None).stats;
+	assert_eq!(stats.pruned_by[0], 0);
+	assert!(stats.pruned_by[1] > 0);
+	assert_eq!(stats.pruned_by[2], 0);
+	assert_eq!(stats.pruned_by.iter().sum::<u128>(), stats.pruned.iter().sum::<u128>());
+	// The index is that of the restriction, not any index within it.
+	let seq = Seq { numerators: vec![9, 9, 9, 9], denominator: 10 };
+	let restrictions = [Restriction::TotalSumUpperBound(100.0),
+			    Restriction::Bounds(3, Interval { lb: 0.0, ub: 0.5 })];
+	assert_eq!(seq.failed_restriction(&restrictions, 4), Some(1));
+	assert_eq!(seq.failed_restriction(&restrictions, 3), None);
+    }
+
+    #[test]
+    fn minimize_threshold_finds_the_edge_of_the_provable_range() {
+	// A case which is proved exactly for thresholds up to 0.37.
+	let found = minimize_threshold(1.0, -3.0, 1e-3, |threshold| threshold <= 0.37).unwrap();
+	assert!(found <= 0.37 && found > 0.37 - 1e-3, "found {}", found);
+	assert_eq!(minimize_threshold(0.2, -3.0, 1e-3, |threshold| threshold <= 0.37), Some(0.2));
+	assert_eq!(minimize_threshold(1.0, -3.0, 1e-3, |_threshold| false), None);
+	// And with a real search, the result is proved but a little above it is not.
+	let bounder = crate::prawitz::tests::small_bounder();
+	let mut case = plain_case(0.5, 0.3, 3, 10);
+	case.hypotheses = vec![Hypothesis::Contradiction];
+	let proves = |threshold: f64| {
+	    simulate(bounder, &case.with_threshold(threshold), None, &[], false, false, None)
+		.outcomes.iter().all(|outcome| outcome.proved)
+	};
+	let found = minimize_threshold(case.threshold, -1.0, 0.01, proves).unwrap();
+	assert!(proves(found));
+	assert!(!proves(found + 0.01));
+    }
+
+    #[test]
+    fn numerator_sum_bounds_are_exact_where_floats_round() {
+	// Beyond 2^53 the numerator sum is rounded when converted to a float, so the
+	// float bounds cannot tell it from 1/4 of the denominator.
+	let denominator: u128 = 1 << 60;
+	let quarter: u128 = 1 << 58;
+	let satisfies = |numerator: u128, restriction: Restriction| {
+	    Seq { numerators: vec![numerator, 0], denominator }
+		.could_satisfy_restrictions(&[restriction], 1)
+	};
+	assert!(satisfies(quarter + 1, Restriction::InitialSumUpperBound(1, 0.25)));
+	assert!(!satisfies(quarter + 1, Restriction::InitialSumUpperBoundNum(1, quarter)));
+	assert!(satisfies(quarter, Restriction::InitialSumUpperBoundNum(1, quarter)));
+	// Here a_0 is at most (quarter - 1) / d, which is below 1/4.
+	assert!(satisfies(quarter - 2, Restriction::InitialSumLowerBound(1, 0.25)));
+	assert!(!satisfies(quarter - 2, Restriction::InitialSumLowerBoundNum(1, quarter)));
+	assert!(satisfies(quarter - 1, Restriction::InitialSumLowerBoundNum(1, quarter)));
+    }
+
+    #[test]
+    fn coverage_counts_provable_and_unprovable_cases() {
+	use crate::file_io::{all_cases, tests::{use_temp_root, write_case_file}};
+	use_temp_root("coverage");
+	write_case_file("easy", "-1, 0.3, 3, 10\nContradiction()\n");
+	write_case_file("hard", "0.5, 0.45, 3, 10\nContradiction()\n---\n\
+				-1, 0.3, 3, 10\nContradiction()\n");
+	write_case_file("nothing", "0.5, 0.3, 3, 10\n");
+	let bounder = crate::prawitz::tests::small_bounder();
+	let cases = all_cases().unwrap();
+	let names = cases.iter().map(|(name, _case)| name.as_str()).collect::<Vec<&str>>();
+	assert_eq!(names, ["easy", "hard:0", "hard:1", "nothing"]);
+	let rows = cases.into_iter().filter(|(_name, case)| !case.hypotheses.is_empty())
+	    .map(|(name, case)| (name, simulate(bounder, &case, None, &[], false, false, None)))
+	    .collect::<Vec<_>>();
+	assert_eq!(rows.len(), 3);
+	assert_eq!(failed_cases(&rows), ["hard:0"]);
+    }
+
+    #[test]
+    fn exclude_numerator_skips_only_that_numerator() {
+	let restrictions = [Restriction::ExcludeNumerator(1, 4)];
+	let survivors = (0..10)
+	    .filter(|numerator| Seq { numerators: vec![9, *numerator], denominator: 10 }
+		    .could_satisfy_restrictions(&restrictions, 2))
+	    .collect::<Vec<u128>>();
+	assert_eq!(survivors, [0, 1, 2, 3, 5, 6, 7, 8, 9]);
+	// Nothing is excluded until a_1 is set, and a_0 may be 4.
+	assert!(Seq { numerators: vec![9, 4], denominator: 10 }.could_satisfy_restrictions(&restrictions, 1));
+	assert!(Seq { numerators: vec![4, 3], denominator: 10 }.could_satisfy_restrictions(&restrictions, 2));
+    }
 }
@@ -1,11 +1,13 @@
 use std::fmt;
 
+use serde::{Deserialize, Serialize};
+
 use crate::util::*;
 
 /**
  * A utility structure for storing intervals of floats.
  */
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Interval {
     pub lb: f64,
     pub ub: f64,
@@ -15,23 +17,71 @@ pub struct Interval {
  * Represents a restriction on the values of a_i that we may wish to enforce.
  * For example, a_0 + a_1 + a_2 < 1
  */
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Restriction {
     InitialSumUpperBound(usize, f64),
     InitialSumLowerBound(usize, f64),
+    // As above, but with the bound given as a numerator over the denominator.
+    InitialSumUpperBoundNum(usize, u128),
+    InitialSumLowerBoundNum(usize, u128),
     MidSumUpperBound(usize, usize, f64),
     Bounds(usize, Interval),
+    RangeBounds(usize, usize, Interval),
+    PartialVarianceBounds(usize, Interval),
+    PrefixVarianceFractionLowerBound(usize, f64),
+    Equals(usize, u128),
+    ExcludeNumerator(usize, u128),
+    EqualPrefix(usize),
+    DistinctValuesUpperBound(usize),
+    ZeroAfter(usize),
+    MaxCoefLowerBound(f64),
+    TailMaxUpperBound(usize, f64),
+    TotalSumUpperBound(f64),
+    CloseCoefficients(usize, usize, f64),
+    LinearBounds(Vec<(usize, f64)>, f64, f64),
+    GeometricDecay(usize, f64),
+    // The inner restriction is only checked while the number of a_i set so far
+    // lies between the two depths, inclusive.
+    Between(usize, usize, Box<Restriction>),
 }
 
 impl Interval {
     pub const UNIT: Interval = Interval { lb: 0.0, ub: 1.0 };
 
+    /**
+     * The intersection of this interval and another, which may be empty.
+     */
+    pub fn intersect(&self, other: &Interval) -> Interval {
+	Interval { lb: self.lb.max(other.lb), ub: self.ub.min(other.ub) }
+    }
+
     /**
      * Mutates this interval to be the intersection of itself and another interval.
      */
     pub fn intersect_inplace(&mut self, other: &Interval) {
-	self.lb = self.lb.max(other.lb);
-	self.ub = self.ub.min(other.ub);
+	*self = self.intersect(other);
+    }
+
+    /**
+     * Whether x lies in this interval, including at either end.
+     */
+    pub fn contains(&self, x: f64) -> bool {
+	self.lb <= x && x <= self.ub
+    }
+
+    /**
+     * Whether no value lies in this interval, i.e. lb > ub. An interval with
+     * lb == ub contains just that point, so is not empty.
+     */
+    pub fn is_empty(&self) -> bool {
+	self.lb > self.ub
+    }
+
+    /**
+     * The length of this interval, which is negative if it is empty.
+     */
+    pub fn width(&self) -> f64 {
+	self.ub - self.lb
     }
 }
 
@@ -43,27 +93,130 @@ impl Restriction {
 	fn parse_float(text: &str) -> f64 {
 	    text.trim().parse().unwrap()
 	}
+	fn parse_numerator(text: &str) -> u128 {
+	    text.trim().parse().unwrap()
+	}
 	let (func, args) = parse_function_like(text);
+	// Checks that there are enough arguments, so that a malformed line in a case
+	// file gives a useful message rather than an index out of bounds.
+	let expect_args = |count: usize| {
+	    if args.len() < count {
+		panic!("{} expects {} argument{}, but got {}: {}", func, count,
+		       if count == 1 { "" } else { "s" }, args.len(), text.trim());
+	    }
+	};
 	use Restriction::*;
-	match func.trim().to_lowercase().as_str() {
+	match canonical_name(func).as_str() {
 	    "initialsumupperbound" => {
+		expect_args(2);
 		InitialSumUpperBound(parse_index(args[0]), parse_float(args[1]))
 	    }
 	    "initialsumlowerbound" => {
+		expect_args(2);
 		InitialSumLowerBound(parse_index(args[0]), parse_float(args[1]))
 	    }
+	    "initialsumupperboundnum" => {
+		expect_args(2);
+		InitialSumUpperBoundNum(parse_index(args[0]), parse_numerator(args[1]))
+	    }
+	    "initialsumlowerboundnum" => {
+		expect_args(2);
+		InitialSumLowerBoundNum(parse_index(args[0]), parse_numerator(args[1]))
+	    }
 	    "midsumupperbound" => {
+		expect_args(3);
 		MidSumUpperBound(parse_index(args[0]),
 				 parse_index(args[1]),
 				 parse_float(args[2]))
 	    }
 	    "bounds" => {
+		expect_args(3);
 		let interval = Interval {
 		    lb: parse_float(args[1]),
 		    ub: parse_float(args[2]),
 		};
 		Bounds(parse_index(args[0]), interval)
 	    }
+	    "rangebounds" => {
+		expect_args(4);
+		let interval = Interval {
+		    lb: parse_float(args[2]),
+		    ub: parse_float(args[3]),
+		};
+		RangeBounds(parse_index(args[0]), parse_index(args[1]), interval)
+	    }
+	    "partialvariancebounds" => {
+		expect_args(3);
+		let interval = Interval {
+		    lb: parse_float(args[1]),
+		    ub: parse_float(args[2]),
+		};
+		PartialVarianceBounds(parse_index(args[0]), interval)
+	    }
+	    "prefixvariancefractionlowerbound" => {
+		expect_args(2);
+		PrefixVarianceFractionLowerBound(parse_index(args[0]), parse_float(args[1]))
+	    }
+	    "equals" => {
+		expect_args(2);
+		Equals(parse_index(args[0]), parse_numerator(args[1]))
+	    }
+	    "excludenumerator" => {
+		expect_args(2);
+		ExcludeNumerator(parse_index(args[0]), parse_numerator(args[1]))
+	    }
+	    "equalprefix" => {
+		expect_args(1);
+		EqualPrefix(parse_index(args[0]))
+	    }
+	    "distinctvaluesupperbound" => {
+		expect_args(1);
+		DistinctValuesUpperBound(parse_index(args[0]))
+	    }
+	    "zeroafter" => {
+		expect_args(1);
+		ZeroAfter(parse_index(args[0]))
+	    }
+	    "maxcoeflowerbound" => {
+		expect_args(1);
+		MaxCoefLowerBound(parse_float(args[0]))
+	    }
+	    "tailmaxupperbound" => {
+		expect_args(2);
+		TailMaxUpperBound(parse_index(args[0]), parse_float(args[1]))
+	    }
+	    "totalsumupperbound" => {
+		expect_args(1);
+		TotalSumUpperBound(parse_float(args[0]))
+	    }
+	    "closecoefficients" => {
+		expect_args(3);
+		CloseCoefficients(parse_index(args[0]), parse_index(args[1]),
+				  parse_float(args[2]))
+	    }
+	    "geometricdecay" => {
+		expect_args(2);
+		GeometricDecay(parse_index(args[0]), parse_float(args[1]))
+	    }
+	    "between" => {
+		expect_args(3);
+		Between(parse_index(args[0]), parse_index(args[1]),
+			Box::new(Restriction::of_string(args[2])))
+	    }
+	    "linearbounds" => {
+		expect_args(3);
+		let terms = split_list(args[0].trim().trim_start_matches('[').trim_end_matches(']'))
+		    .iter()
+		    .map(|term| {
+			let pair = split_list(term.trim_start_matches('('));
+			if pair.len() != 2 {
+			    panic!("Expected each term of {} to be (index, weight)!", text.trim());
+			}
+			(parse_index(pair[0]), parse_float(pair[1]))
+		    })
+		    .collect();
+		LinearBounds(terms, parse_float(args[1]), parse_float(args[2]))
+	    }
 	    &_ => panic!("Unknown restriction!")
 	}
     }
@@ -74,3 +227,83 @@ impl fmt::Debug for Interval {
         write!(f, "{}, {}", self.lb, self.ub)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parses_as(text: &str, expected: &str) {
+	assert_eq!(format!("{:?}", Restriction::of_string(text)), expected, "parsing {:?}", text);
+    }
+
+    #[test]
+    fn messy_restrictions_parse() {
+	parses_as("  Bounds ( 3 ,0.1,  0.5 ) ", "Bounds(3, 0.1, 0.5)");
+	parses_as("bounds(3,0.1,0.5)", "Bounds(3, 0.1, 0.5)");
+	parses_as("initial_sum_upper_bound(2,\t1.5)", "InitialSumUpperBound(2, 1.5)");
+	parses_as("MIDSUMUPPERBOUND( 1, 2, 0.7 )  ", "MidSumUpperBound(1, 2, 0.7)");
+	parses_as("Between(1, 3, Bounds( 0 , 0.1 , 0.2 ))", "Between(1, 3, Bounds(0, 0.1, 0.2))");
+	parses_as("LinearBounds([ (0, 1.0) , (2,-0.5) ], 0, 1)",
+		  "LinearBounds([(0, 1.0), (2, -0.5)], 0.0, 1.0)");
+	parses_as("PrefixVarianceFractionLowerBound(1, 0.5)",
+		  "PrefixVarianceFractionLowerBound(1, 0.5)");
+    }
+
+    #[test]
+    fn empty_point_and_normal_intervals() {
+	let empty = Interval { lb: 0.6, ub: 0.4 };
+	assert!(empty.is_empty());
+	assert!(!empty.contains(0.5) && !empty.contains(0.4) && !empty.contains(0.6));
+	assert!(empty.width() < 0.0);
+	let point = Interval { lb: 0.5, ub: 0.5 };
+	assert!(!point.is_empty());
+	assert!(point.contains(0.5));
+	assert!(!point.contains(0.5 + f64::EPSILON) && !point.contains(0.5 - f64::EPSILON));
+	assert_eq!(point.width(), 0.0);
+	let normal = Interval { lb: 0.25, ub: 0.75 };
+	assert!(!normal.is_empty());
+	assert!(normal.contains(0.25) && normal.contains(0.5) && normal.contains(0.75));
+	assert!(!normal.contains(0.2) && !normal.contains(0.8));
+	assert_eq!(normal.width(), 0.5);
+	assert!(normal.intersect(&Interval { lb: 0.8, ub: 1.0 }).is_empty());
+	assert_eq!(normal.intersect(&Interval::UNIT).width(), 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bounds expects 3 arguments, but got 2: Bounds(0, 0.5)")]
+    fn bounds_without_an_interval_names_the_restriction() {
+	Restriction::of_string("Bounds(0, 0.5)");
+    }
+
+    #[test]
+    fn every_restriction_with_too_few_arguments_names_itself() {
+	let cases = [
+	    ("InitialSumUpperBound(2)", "InitialSumUpperBound expects 2 arguments, but got 1"),
+	    ("InitialSumLowerBound(2)", "InitialSumLowerBound expects 2 arguments, but got 1"),
+	    ("InitialSumUpperBoundNum(2)", "InitialSumUpperBoundNum expects 2 arguments, but got 1"),
+	    ("InitialSumLowerBoundNum(2)", "InitialSumLowerBoundNum expects 2 arguments, but got 1"),
+	    ("MidSumUpperBound(1, 2)", "MidSumUpperBound expects 3 arguments, but got 2"),
+	    ("RangeBounds(1, 2, 0.5)", "RangeBounds expects 4 arguments, but got 3"),
+	    ("PartialVarianceBounds(1, 0.5)", "PartialVarianceBounds expects 3 arguments, but got 2"),
+	    ("PrefixVarianceFractionLowerBound(1)",
+	     "PrefixVarianceFractionLowerBound expects 2 arguments, but got 1"),
+	    ("Equals(1)", "Equals expects 2 arguments, but got 1"),
+	    ("ExcludeNumerator(1)", "ExcludeNumerator expects 2 arguments, but got 1"),
+	    ("EqualPrefix", "EqualPrefix expects 1 argument, but got 0"),
+	    ("DistinctValuesUpperBound", "DistinctValuesUpperBound expects 1 argument, but got 0"),
+	    ("ZeroAfter", "ZeroAfter expects 1 argument, but got 0"),
+	    ("MaxCoefLowerBound", "MaxCoefLowerBound expects 1 argument, but got 0"),
+	    ("TailMaxUpperBound(1)", "TailMaxUpperBound expects 2 arguments, but got 1"),
+	    ("TotalSumUpperBound", "TotalSumUpperBound expects 1 argument, but got 0"),
+	    ("CloseCoefficients(0, 1)", "CloseCoefficients expects 3 arguments, but got 2"),
+	    ("GeometricDecay(1)", "GeometricDecay expects 2 arguments, but got 1"),
+	    ("Between(1, 3)", "Between expects 3 arguments, but got 2"),
+	    ("LinearBounds([(0, 1.0)], 0)", "LinearBounds expects 3 arguments, but got 2"),
+	];
+	for (text, expected) in cases {
+	    let payload = std::panic::catch_unwind(|| Restriction::of_string(text)).unwrap_err();
+	    let message = payload.downcast_ref::<String>().unwrap();
+	    assert!(message.starts_with(expected), "{:?} gave {:?}", text, message);
+	}
+    }
+}
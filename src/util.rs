@@ -1,30 +1,115 @@
 /**
- * Two utility functions for parsing user-inputted strings.
+ * Utility functions for parsing user-inputted strings, and some numerics.
  */
 
+/**
+ * Splits a comma-separated list at the top level of brackets, either round or
+ * square. Each argument is trimmed of whitespace, so callers need not do so
+ * themselves.
+ */
 pub fn split_list(text: &str) -> Vec<&str> {
     let mut depth = 0;
     let mut last_index = 0;
     let mut args = vec![];
     for (i, c) in text.as_bytes().iter().enumerate() {
-        if *c == '(' as u8 {
+	if *c == '(' as u8 || *c == b'[' {
             depth += 1;
-        } else if *c == ')' as u8 {
+	} else if *c == ')' as u8 || *c == b']' {
             depth -= 1;
         } else if *c == ',' as u8 && depth == 0 {
-            args.push(&text[last_index..i]);
+	    args.push(text[last_index..i].trim());
             last_index = i + 1;
         }
     }
-    args.push(&text[last_index..text.len()].trim().trim_end_matches(')'));
+    args.push(text[last_index..text.len()].trim().trim_end_matches(')').trim());
     args
 }
 
 pub fn parse_function_like(text: &str) -> (&str, Vec<&str>) {
     match text.split_once('(') {
         Some((func, args_string)) => {
-            (func, split_list(args_string))
+	    (func.trim(), split_list(args_string))
         }
-        None => (text, vec![]),
+	None => (text.trim(), vec![]),
+    }
+}
+
+/**
+ * Puts the name of a function-like directive into a canonical form, so that
+ * e.g. InitialSumUpperBound and initial_sum_upper_bound are treated the same.
+ */
+pub fn canonical_name(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', "")
+}
+
+/**
+ * The number n of equal terms 1/sqrt(n) whose largest coefficient is a, which need
+ * not be a whole number.
+ */
+pub fn equal_terms(a: f64) -> f64 {
+    1.0 / (a * a)
+}
+
+/**
+ * Formats a float to the given number of decimal places, or in full if none is
+ * given. The rounding is done by round (e.g. f64::floor), so that a lower bound
+ * can be printed without being rounded above its true value.
+ */
+pub fn format_float(x: f64, precision: Option<usize>, round: fn(f64) -> f64) -> String {
+    match precision {
+	Some(precision) => {
+	    let scale = 10f64.powi(precision as i32);
+	    format!("{:.*}", precision, round(x * scale) / scale)
+	}
+	None => x.to_string(),
+    }
+}
+
+/**
+ * The complementary error function, using the Chebyshev approximation from
+ * Numerical Recipes (erfcc), which has fractional error below 1.2e-7 everywhere.
+ */
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let ans = t * (-z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196
+	+ t * (0.09678418 + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398
+	+ t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))))).exp();
+    if x >= 0.0 {
+	ans
+    } else {
+	2.0 - ans
+    }
+}
+
+/**
+ * Pr[Z > x] for a standard normal Z.
+ */
+pub fn gaussian_tail(x: f64) -> f64 {
+    0.5 * erfc(x / std::f64::consts::SQRT_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precision_sets_the_width() {
+	let x = 0.123456789;
+	assert_eq!(format_float(x, Some(2), f64::floor), "0.12");
+	assert_eq!(format_float(x, Some(4), f64::floor), "0.1234");
+	assert_eq!(format_float(x, Some(4), f64::ceil), "0.1235");
+	assert_eq!(format_float(x, None, f64::floor), "0.123456789");
+	// Trailing zeros are kept, so the width does not depend on the value.
+	assert_eq!(format_float(0.5, Some(3), f64::floor), "0.500");
+    }
+
+    #[test]
+    fn equal_terms_of_a_few_coefficients() {
+	assert_eq!(equal_terms(1.0), 1.0);
+	assert_eq!(equal_terms(0.5), 4.0);
+	assert_eq!(equal_terms(0.25), 16.0);
+	assert_eq!(equal_terms(0.1).round(), 100.0);
+	assert!((equal_terms(1.0 / 3.0f64.sqrt()) - 3.0).abs() < 1e-12);
     }
 }
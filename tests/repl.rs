@@ -0,0 +1,47 @@
+use std::{fs, io::Write, process::{Command, Stdio}};
+
+/**
+ * Runs the REPL on the given input from an empty directory, so that it finds no
+ * cases or tables there, and returns everything it printed.
+ */
+fn run_repl(name: &str, input: &str) -> String {
+    let dir = std::env::temp_dir()
+	.join(format!("rademacher-repl-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rademacher-prod"))
+	.current_dir(&dir)
+	.stdin(Stdio::piped())
+	.stdout(Stdio::piped())
+	.spawn()
+	.unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "the REPL exited with {}", output.status);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn missing_arguments_are_reported() {
+    let output = run_repl("missing_arguments", "run\nrun()\nd(0.5)\n");
+    assert_eq!(output.matches("Expected format: run(file)").count(), 2);
+    assert!(output.contains("D takes two arguments, but got 1! Expected format: D(a,x)"));
+}
+
+#[test]
+fn terms_of_a_coefficient() {
+    let output = run_repl("terms", "terms(0.5)\nterms(0)\nterms(x)\n");
+    assert!(output.contains("n = 1/a^2 = 4, i.e. about 4 equal terms"));
+    assert!(output.contains("Need 0 < a <= 1!"));
+    assert!(output.contains("Expected format: terms(a)"));
+}
+
+#[test]
+fn arguments_of_d_are_checked_one_by_one() {
+    let output = run_repl("d_arguments", "D(x, 0.5)\nD(0.5, y)\nD(0.5)\nD()\nD(0.5, 0.1, 2)\n");
+    assert!(output.contains("Failed to parse the first argument a = x: invalid float literal! Expected format: D(a,x)"));
+    assert!(output.contains("Failed to parse the second argument x = y: invalid float literal! Expected format: D(a,x)"));
+    assert!(output.contains("D takes two arguments, but got 1! Expected format: D(a,x)"));
+    assert!(output.contains("D takes two arguments, but got 0! Expected format: D(a,x)"));
+    assert!(output.contains("D takes two arguments, but got 3! Expected format: D(a,x)"));
+}